@@ -0,0 +1,245 @@
+//! Decode SGTL5000 register dumps on the host
+//!
+//! ```text
+//! sgtl5000-regdump DUMP
+//! sgtl5000-regdump diff OLD NEW
+//! ```
+//!
+//! Text dumps have one register per line: its hex address followed by
+//! its hex value, as printed by `dump_registers()` or a recorder log
+//! (`W 0030 7060`). Indented lines and `#` comments are skipped.
+//!
+//! Binary dumps are consecutive big-endian address and value pairs,
+//! the bytes of a register write as exported by a logic analyser.
+//!
+//! A register that appears more than once keeps its last value.
+
+extern crate sgtl5000;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::{env, fs, process};
+
+use sgtl5000::registers::{register_name, write_fields, write_register};
+
+/// Register values by address
+type Dump = BTreeMap<u16, u16>;
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError {
+    /// Text line without address and value
+    Line(usize),
+    /// Binary dump that is not made of 4-byte pairs
+    Length(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Line(line) =>
+                write!(f, "line {}: expected register address and value", line),
+            ParseError::Length(len) =>
+                write!(f, "{} bytes is not a whole number of address/value pairs", len),
+        }
+    }
+}
+
+/// Hex number, optionally with `0x` prefix or `:` suffix
+fn parse_word(word: &str) -> Option<u16> {
+    let word = word.trim_end_matches(':');
+    let word = word.strip_prefix("0x")
+        .or_else(|| word.strip_prefix("0X"))
+        .unwrap_or(word);
+    u16::from_str_radix(word, 16).ok()
+}
+
+fn parse_text(text: &str) -> Result<Dump, ParseError> {
+    let mut dump = Dump::new();
+    for (index, line) in text.lines().enumerate() {
+        // Decoded fields
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace().peekable();
+        // Access of a recorder log
+        if let Some(&"R") | Some(&"W") = words.peek() {
+            words.next();
+        }
+        let addr = words.next().and_then(parse_word);
+        let value = words.next().and_then(parse_word);
+        match (addr, value) {
+            (Some(addr), Some(value)) => {
+                dump.insert(addr, value);
+            }
+            _ => return Err(ParseError::Line(index + 1)),
+        }
+    }
+    Ok(dump)
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Dump, ParseError> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(ParseError::Length(bytes.len()));
+    }
+
+    let mut dump = Dump::new();
+    for pair in bytes.chunks(4) {
+        let addr = u16::from(pair[0]) << 8 | u16::from(pair[1]);
+        let value = u16::from(pair[2]) << 8 | u16::from(pair[3]);
+        dump.insert(addr, value);
+    }
+    Ok(dump)
+}
+
+/// Text if it reads like text, binary otherwise
+fn parse(bytes: &[u8]) -> Result<Dump, ParseError> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if text.chars().all(|c| !c.is_control() || c.is_whitespace()) =>
+            parse_text(text),
+        _ =>
+            parse_binary(bytes),
+    }
+}
+
+fn decode(dump: &Dump) -> String {
+    let mut out = String::new();
+    for (&addr, &value) in dump {
+        write_register(&mut out, addr, value).unwrap();
+    }
+    out
+}
+
+/// Decoded fields as `(name, value)` pairs
+fn fields(addr: u16, value: u16) -> Vec<(String, String)> {
+    let mut out = String::new();
+    write_fields(&mut out, addr, value).unwrap();
+    out.lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, ": ");
+            Some((parts.next()?.to_string(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Registers and fields that differ between two dumps
+fn diff(old: &Dump, new: &Dump) -> String {
+    let addrs: BTreeSet<u16> = old.keys().chain(new.keys()).cloned().collect();
+    let mut out = String::new();
+    for addr in addrs {
+        let name = register_name(addr).unwrap_or("?");
+        match (old.get(&addr), new.get(&addr)) {
+            (Some(old), Some(new)) if old == new => {}
+            (Some(&old), Some(&new)) => {
+                writeln!(out, "{:04X} {:04X} -> {:04X} {}", addr, old, new, name).unwrap();
+                for ((field, old), (_, new)) in fields(addr, old).into_iter().zip(fields(addr, new)) {
+                    if old != new {
+                        writeln!(out, "    {}: {} -> {}", field, old, new).unwrap();
+                    }
+                }
+            }
+            (Some(&old), None) =>
+                writeln!(out, "{:04X} {:04X} -> ---- {}", addr, old, name).unwrap(),
+            (None, Some(&new)) =>
+                writeln!(out, "{:04X} ---- -> {:04X} {}", addr, new, name).unwrap(),
+            (None, None) => {}
+        }
+    }
+    out
+}
+
+fn load(path: &str) -> Dump {
+    let bytes = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(2);
+    });
+    parse(&bytes).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(2);
+    })
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: sgtl5000-regdump DUMP");
+    eprintln!("       sgtl5000-regdump diff OLD NEW");
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["diff", old, new] => {
+            let changes = diff(&load(old), &load(new));
+            print!("{}", changes);
+            // Like diff(1)
+            if !changes.is_empty() {
+                process::exit(1);
+            }
+        }
+        [path] if path != "diff" =>
+            print!("{}", decode(&load(path))),
+        _ =>
+            usage(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_text() {
+        let text = "\
+# CHIP_CLK_CTRL
+0004 0008 CHIP_CLK_CTRL
+    rate_mode: Div1
+    sys_fs: Hz48000
+0x0010: 0x3c3c
+R 0000 A011
+W 0004 000C
+";
+        let dump = parse(text.as_bytes()).unwrap();
+        assert_eq!(3, dump.len());
+        assert_eq!(Some(&0x000C), dump.get(&0x0004));
+        assert_eq!(Some(&0x3C3C), dump.get(&0x0010));
+        assert_eq!(Some(&0xA011), dump.get(&0x0000));
+
+        assert_eq!(Err(ParseError::Line(2)), parse(b"0004 0008\n0006\n"));
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let dump = parse(&[0x00, 0x30, 0x70, 0x60, 0x00, 0x04, 0x00, 0x08]).unwrap();
+        assert_eq!(Some(&0x7060), dump.get(&0x0030));
+        assert_eq!(Some(&0x0008), dump.get(&0x0004));
+
+        assert_eq!(Err(ParseError::Length(3)), parse(&[0x00, 0x30, 0x70]));
+    }
+
+    #[test]
+    fn test_decode_roundtrip() {
+        let mut dump = Dump::new();
+        dump.insert(0x0000, 0xA011);
+        dump.insert(0x0004, 0x0008);
+        let text = decode(&dump);
+        assert!(text.starts_with("0000 A011 CHIP_ID\n    partid: 0xa0\n"));
+        assert_eq!(Ok(dump), parse(text.as_bytes()));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = parse(b"0004 0008\n0010 3C3C\n0030 7060\n").unwrap();
+        let new = parse(b"0004 000C\n0010 3C3C\n0032 5000\n").unwrap();
+        assert_eq!("\
+0004 0008 -> 000C CHIP_CLK_CTRL
+    sys_fs: Hz48000 -> Hz96000
+0030 7060 -> ---- CHIP_ANA_POWER
+0032 ---- -> 5000 CHIP_PLL_CTRL
+", diff(&old, &new));
+        assert_eq!("", diff(&old, &old));
+    }
+}
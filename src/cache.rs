@@ -0,0 +1,207 @@
+//! Shadow register cache
+//!
+//! [`Cached`] wraps a control port and keeps a copy of every
+//! register, so that read-modify-write cycles only cost the write.
+//! Writes always go through to the codec immediately.
+
+use crate::interface::RegisterInterface;
+use crate::registers::{register_index, reset_values, written_value, I2cRegister, REGISTER_MAP};
+use crate::registers::{ChipAdcdacCtrl, ChipAnaStatus, ChipId};
+
+/// Registers whose content changes by itself or that must really
+/// be read from the chip: CHIP_ID, CHIP_ADCDAC_CTRL (volume busy
+/// flags) and CHIP_ANA_STATUS
+const UNCACHED: [u16; 3] = [ChipId::ADDR, ChipAdcdacCtrl::ADDR, ChipAnaStatus::ADDR];
+
+/// Control port with a shadow copy of all registers
+pub struct Cached<IF> {
+    interface: IF,
+    values: [u16; REGISTER_MAP.len()],
+    valid: [bool; REGISTER_MAP.len()],
+    /// Bus transactions that were not needed
+    saved: usize,
+}
+
+impl<IF: RegisterInterface> Cached<IF> {
+    /// Seed the cache with reset values, expecting a freshly reset
+    /// codec
+    pub fn new(interface: IF) -> Self {
+        let mut cached = Self::empty(interface);
+        cached.values = reset_values();
+        for (index, &(addr, _, _)) in REGISTER_MAP.iter().enumerate() {
+            cached.valid[index] = !UNCACHED.contains(&addr);
+        }
+        cached
+    }
+
+    /// Seed the cache by reading back every register once
+    pub fn with_readback(interface: IF) -> Result<Self, IF::Error> {
+        let mut cached = Self::empty(interface);
+        cached.sync()?;
+        Ok(cached)
+    }
+
+    fn empty(interface: IF) -> Self {
+        Cached {
+            interface,
+            values: [0; REGISTER_MAP.len()],
+            valid: [false; REGISTER_MAP.len()],
+            saved: 0,
+        }
+    }
+
+    /// Give back the control port
+    pub fn release(self) -> IF {
+        self.interface
+    }
+
+    /// Refresh all cached registers from the codec
+    pub fn sync(&mut self) -> Result<(), IF::Error> {
+        for (index, &(addr, _, _)) in REGISTER_MAP.iter().enumerate() {
+            if !UNCACHED.contains(&addr) {
+                self.values[index] = self.interface.read_register(addr)?;
+                self.valid[index] = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget all cached values, e.g. after resetting the codec. The
+    /// next read of each register goes to the bus.
+    pub fn invalidate(&mut self) {
+        self.valid = [false; REGISTER_MAP.len()];
+    }
+
+    /// Number of register reads that were served from the cache
+    pub fn saved_transactions(&self) -> usize {
+        self.saved
+    }
+
+    fn index(addr: u16) -> Option<usize> {
+        if UNCACHED.contains(&addr) {
+            return None;
+        }
+        register_index(addr)
+    }
+}
+
+impl<IF: RegisterInterface> RegisterInterface for Cached<IF> {
+    type Error = IF::Error;
+
+    fn read_register(&mut self, addr: u16) -> Result<u16, Self::Error> {
+        match Self::index(addr) {
+            Some(index) if self.valid[index] => {
+                self.saved += 1;
+                Ok(self.values[index])
+            }
+            Some(index) => {
+                let value = self.interface.read_register(addr)?;
+                self.values[index] = value;
+                self.valid[index] = true;
+                Ok(value)
+            }
+            None =>
+                self.interface.read_register(addr),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u16) -> Result<(), Self::Error> {
+        self.interface.write_register(addr, value)?;
+
+        if let Some(index) = Self::index(addr) {
+            self.values[index] = written_value(index, self.values[index], value);
+        }
+        Ok(())
+    }
+
+    fn can_read(&self) -> bool {
+        self.interface.can_read()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::control::SGTL5000Control;
+    use crate::interface::{Address, I2cInterface};
+    use crate::recorder::{Access, Recorder};
+    use crate::sim::Simulator;
+
+    fn bus_reads(recorder: &Recorder<Simulator>) -> usize {
+        recorder.log().entries().iter()
+            .filter(|entry| entry.access == Access::Read)
+            .count()
+    }
+
+    #[test]
+    fn test_init_cached() {
+        let interface = I2cInterface::new(Recorder::new(Simulator::new()), Address::Adr0Low);
+        let control = SGTL5000Control::with_interface(Cached::new(interface)).unwrap();
+        let cached = control.release_interface();
+        let saved = cached.saved_transactions();
+        let recorder = cached.release().release();
+
+        // Only CHIP_ID and CHIP_ADCDAC_CTRL went over the bus
+        assert_eq!(2, bus_reads(&recorder));
+        assert!(saved > 0);
+
+        // Same result as without cache
+        let uncached = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap().release();
+        let sim = recorder.release();
+        for &(addr, _, _) in REGISTER_MAP.iter() {
+            assert_eq!(uncached.peek(addr), sim.peek(addr), "register {:04X}", addr);
+        }
+    }
+
+    #[test]
+    fn test_readback() {
+        let mut sim = Simulator::new();
+        sim.poke(0x0010, 0x1234).unwrap();
+        let interface = I2cInterface::new(Recorder::new(sim), Address::Adr0Low);
+        let mut cached = Cached::with_readback(interface).unwrap();
+        let reads = bus_reads(cached.interface.i2c());
+        assert_eq!(REGISTER_MAP.len() - UNCACHED.len(), reads);
+
+        assert_eq!(Ok(0x1234), cached.read_register(0x0010));
+        assert_eq!(reads, bus_reads(cached.interface.i2c()));
+        assert_eq!(1, cached.saved_transactions());
+    }
+
+    #[test]
+    fn test_write_through() {
+        let interface = I2cInterface::new(Recorder::new(Simulator::new()), Address::Adr0Low);
+        let mut cached = Cached::new(interface);
+        cached.write_register(0x0002, 0xFFFF).unwrap();
+        // Read-only bits are not cached as written
+        assert_eq!(Ok(0x0073), cached.read_register(0x0002));
+        assert_eq!(0, bus_reads(cached.interface.i2c()));
+        let sim = cached.release().release().release();
+        assert_eq!(Ok(0x0073), sim.peek(0x0002));
+    }
+
+    #[test]
+    fn test_invalidate_sync() {
+        let interface = I2cInterface::new(Recorder::new(Simulator::new()), Address::Adr0Low);
+        let mut cached = Cached::new(interface);
+        cached.invalidate();
+        cached.read_register(0x0030).unwrap();
+        cached.read_register(0x0030).unwrap();
+        assert_eq!(1, bus_reads(cached.interface.i2c()));
+        assert_eq!(1, cached.saved_transactions());
+
+        cached.sync().unwrap();
+        assert_eq!(1 + REGISTER_MAP.len() - UNCACHED.len(), bus_reads(cached.interface.i2c()));
+    }
+
+    #[test]
+    fn test_uncached() {
+        let interface = I2cInterface::new(Recorder::new(Simulator::new()), Address::Adr0Low);
+        let mut cached = Cached::new(interface);
+        for &addr in UNCACHED.iter() {
+            cached.read_register(addr).unwrap();
+            cached.read_register(addr).unwrap();
+        }
+        assert_eq!(2 * UNCACHED.len(), bus_reads(cached.interface.i2c()));
+        assert_eq!(0, cached.saved_transactions());
+    }
+}
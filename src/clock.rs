@@ -0,0 +1,370 @@
+//! Sample rate and SYS_MCLK setup in CHIP_CLK_CTRL
+//!
+//! The codec runs at the system sample rate SYS_FS (32, 44.1, 48 or
+//! 96 kHz), divided by RATE_MODE for lower rates down to 8 kHz.
+//! SYS_MCLK must be 256, 384 or 512 times SYS_FS, any other
+//! frequency goes through the PLL.
+
+use crate::registers::{ChipClkCtrl, I2cRegister, MclkRatio, RateMode, SampleRate};
+
+/// Lowest SYS_MCLK input frequency
+pub const MCLK_MIN: u32 = 8_000_000;
+/// Highest SYS_MCLK input frequency
+pub const MCLK_MAX: u32 = 27_000_000;
+
+/// PLL output for SYS_FS of 44.1 kHz
+const PLL_OUT_44K1: u32 = 180_633_600;
+/// PLL output for the other SYS_FS
+const PLL_OUT: u32 = 196_608_000;
+/// SYS_MCLK above this is halved before the PLL
+const PLL_DIV2_THRESHOLD: u32 = 17_000_000;
+/// FRAC_DIVISOR resolution
+const PLL_FRAC_STEPS: u64 = 2048;
+
+/// How long to wait for the PLL to lock: `polls` intervals of
+/// `interval_us`, with CHIP_ANA_STATUS read before and after each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PllLock {
+    pub polls: u32,
+    pub interval_us: u32,
+}
+
+impl Default for PllLock {
+    /// 10 ms in 500 µs steps
+    fn default() -> Self {
+        PllLock {
+            polls: 20,
+            interval_us: 500,
+        }
+    }
+}
+
+impl PllLock {
+    /// Total time to wait
+    pub fn timeout_us(&self) -> u32 {
+        self.polls.saturating_mul(self.interval_us)
+    }
+}
+
+/// SYS_MCLK input frequency relative to SYS_FS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mclk {
+    Fs256,
+    Fs384,
+    Fs512,
+    /// Any SYS_MCLK frequency in Hz, through the PLL
+    Pll(u32),
+}
+
+impl Mclk {
+    /// SYS_MCLK frequency for `sys_fs`
+    pub fn hz(self, sys_fs: SampleRate) -> u32 {
+        match self {
+            Mclk::Fs256 => 256 * sys_fs.hz(),
+            Mclk::Fs384 => 384 * sys_fs.hz(),
+            Mclk::Fs512 => 512 * sys_fs.hz(),
+            Mclk::Pll(hz) => hz,
+        }
+    }
+}
+
+impl From<Mclk> for MclkRatio {
+    fn from(mclk: Mclk) -> Self {
+        match mclk {
+            Mclk::Fs256 => MclkRatio::Fs256,
+            Mclk::Fs384 => MclkRatio::Fs384,
+            Mclk::Fs512 => MclkRatio::Fs512,
+            Mclk::Pll(_) => MclkRatio::Pll,
+        }
+    }
+}
+
+impl SampleRate {
+    pub fn hz(self) -> u32 {
+        match self {
+            SampleRate::Hz32000 => 32_000,
+            SampleRate::Hz44100 => 44_100,
+            SampleRate::Hz48000 => 48_000,
+            SampleRate::Hz96000 => 96_000,
+        }
+    }
+}
+
+impl RateMode {
+    pub fn divisor(self) -> u32 {
+        match self {
+            RateMode::Div1 => 1,
+            RateMode::Div2 => 2,
+            RateMode::Div4 => 4,
+            RateMode::Div6 => 6,
+        }
+    }
+}
+
+const SYS_FS: [SampleRate; 4] = [
+    SampleRate::Hz32000,
+    SampleRate::Hz44100,
+    SampleRate::Hz48000,
+    SampleRate::Hz96000,
+];
+
+const RATE_MODES: [RateMode; 4] = [
+    RateMode::Div1,
+    RateMode::Div2,
+    RateMode::Div4,
+    RateMode::Div6,
+];
+
+/// Actual sample rate in Hz
+pub fn sample_rate_hz(sys_fs: SampleRate, rate_mode: RateMode) -> u32 {
+    sys_fs.hz() / rate_mode.divisor()
+}
+
+/// All SYS_FS and RATE_MODE giving a sample rate in Hz, smallest
+/// divisor first
+fn sample_rates(hz: u32) -> impl Iterator<Item=(SampleRate, RateMode)> {
+    RATE_MODES.iter()
+        .flat_map(|&rate_mode| SYS_FS.iter().map(move |&sys_fs| (sys_fs, rate_mode)))
+        .filter(move |&(sys_fs, rate_mode)| hz.checked_mul(rate_mode.divisor()) == Some(sys_fs.hz()))
+}
+
+/// SYS_FS and RATE_MODE for a sample rate in Hz, preferring the
+/// smallest divisor
+pub fn sample_rate(hz: u32) -> Option<(SampleRate, RateMode)> {
+    sample_rates(hz).next()
+}
+
+/// PLL settings in CHIP_CLK_TOP_CTRL and CHIP_PLL_CTRL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PllDivisors {
+    pub input_freq_div2: bool,
+    pub int_divisor: u8,
+    pub frac_divisor: u16,
+}
+
+fn pll_output(sys_fs: SampleRate) -> u32 {
+    match sys_fs {
+        SampleRate::Hz44100 => PLL_OUT_44K1,
+        _ => PLL_OUT,
+    }
+}
+
+/// PLL divisors for clocking `sys_fs` from SYS_MCLK at `mclk_hz`
+pub fn pll_divisors(mclk_hz: u32, sys_fs: SampleRate) -> Result<PllDivisors, &'static str> {
+    if !(MCLK_MIN..=MCLK_MAX).contains(&mclk_hz) {
+        return Err("SYS_MCLK must be within 8..27 MHz");
+    }
+    let input_freq_div2 = mclk_hz > PLL_DIV2_THRESHOLD;
+    let input = if input_freq_div2 { mclk_hz / 2 } else { mclk_hz };
+    let output = pll_output(sys_fs);
+    let frac = u64::from(output % input) * PLL_FRAC_STEPS / u64::from(input);
+    Ok(PllDivisors {
+        input_freq_div2,
+        int_divisor: (output / input) as u8,
+        frac_divisor: frac as u16,
+    })
+}
+
+/// A sample rate with the SYS_MCLK it is clocked from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    sys_fs: SampleRate,
+    rate_mode: RateMode,
+    mclk: Mclk,
+}
+
+impl Clock {
+    /// Reject settings the codec cannot be clocked with
+    pub fn new(sys_fs: SampleRate, rate_mode: RateMode, mclk: Mclk) -> Result<Self, &'static str> {
+        match mclk {
+            Mclk::Pll(hz) => {
+                pll_divisors(hz, sys_fs)?;
+            }
+            // Only 256*Fs at 96 kHz
+            _ if mclk.hz(sys_fs) > MCLK_MAX =>
+                return Err("SYS_MCLK above 27 MHz"),
+            _ => {}
+        }
+        Ok(Clock { sys_fs, rate_mode, mclk })
+    }
+
+    /// Clock `sample_rate_hz` from SYS_MCLK at `mclk_hz`, directly if
+    /// it is 256, 384 or 512 times SYS_FS, through the PLL otherwise
+    pub fn from_mclk(mclk_hz: u32, sample_rate_hz: u32) -> Result<Self, &'static str> {
+        for (sys_fs, rate_mode) in sample_rates(sample_rate_hz) {
+            for &mclk in &[Mclk::Fs256, Mclk::Fs384, Mclk::Fs512] {
+                if mclk.hz(sys_fs) == mclk_hz {
+                    if let Ok(clock) = Clock::new(sys_fs, rate_mode, mclk) {
+                        return Ok(clock);
+                    }
+                }
+            }
+        }
+        let (sys_fs, rate_mode) = sample_rate(sample_rate_hz)
+            .ok_or("sample rate not supported")?;
+        Clock::new(sys_fs, rate_mode, Mclk::Pll(mclk_hz))
+    }
+
+    pub fn sys_fs(&self) -> SampleRate {
+        self.sys_fs
+    }
+
+    pub fn rate_mode(&self) -> RateMode {
+        self.rate_mode
+    }
+
+    pub fn mclk(&self) -> Mclk {
+        self.mclk
+    }
+
+    /// Nominal sample rate in Hz
+    pub fn sample_rate_hz(&self) -> u32 {
+        sample_rate_hz(self.sys_fs, self.rate_mode)
+    }
+
+    /// Sample rate in Hz the codec really runs at, which differs from
+    /// the nominal one when the PLL cannot hit its output frequency
+    pub fn exact_sample_rate(&self) -> f64 {
+        let sys_fs = match (self.mclk, self.pll()) {
+            (Mclk::Pll(hz), Some(pll)) => {
+                let input = if pll.input_freq_div2 { f64::from(hz) / 2.0 } else { f64::from(hz) };
+                let divisor = f64::from(pll.int_divisor)
+                    + f64::from(pll.frac_divisor) / PLL_FRAC_STEPS as f64;
+                input * divisor * f64::from(self.sys_fs.hz()) / f64::from(pll_output(self.sys_fs))
+            }
+            _ => f64::from(self.sys_fs.hz()),
+        };
+        sys_fs / f64::from(self.rate_mode.divisor())
+    }
+
+    /// Deviation of the exact from the nominal sample rate in ppm
+    pub fn error_ppm(&self) -> f64 {
+        (self.exact_sample_rate() / f64::from(self.sample_rate_hz()) - 1.0) * 1e6
+    }
+
+    pub fn clk_ctrl(&self) -> ChipClkCtrl {
+        let mut clk_ctrl = ChipClkCtrl::new(0);
+        clk_ctrl.set_sys_fs(self.sys_fs);
+        clk_ctrl.set_rate_mode(self.rate_mode);
+        clk_ctrl.set_mclk_freq(self.mclk.into());
+        clk_ctrl
+    }
+
+    /// Divisors if clocked through the PLL
+    pub fn pll(&self) -> Option<PllDivisors> {
+        match self.mclk {
+            Mclk::Pll(hz) => pll_divisors(hz, self.sys_fs).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_rate() {
+        assert_eq!(Some((SampleRate::Hz48000, RateMode::Div1)), sample_rate(48_000));
+        assert_eq!(Some((SampleRate::Hz96000, RateMode::Div1)), sample_rate(96_000));
+        assert_eq!(Some((SampleRate::Hz44100, RateMode::Div2)), sample_rate(22_050));
+        assert_eq!(Some((SampleRate::Hz48000, RateMode::Div4)), sample_rate(12_000));
+        assert_eq!(Some((SampleRate::Hz32000, RateMode::Div4)), sample_rate(8_000));
+        assert_eq!(Some((SampleRate::Hz32000, RateMode::Div2)), sample_rate(16_000));
+        assert_eq!(None, sample_rate(11_000));
+        for &hz in &[8_000, 11_025, 12_000, 16_000, 22_050, 24_000, 32_000, 44_100, 48_000, 96_000] {
+            let (sys_fs, rate_mode) = sample_rate(hz).unwrap();
+            assert_eq!(hz, sample_rate_hz(sys_fs, rate_mode));
+        }
+    }
+
+    fn clk_ctrl(sys_fs: SampleRate, rate_mode: RateMode, mclk: Mclk) -> Result<u16, &'static str> {
+        Clock::new(sys_fs, rate_mode, mclk).map(|clock| clock.clk_ctrl().to_inner())
+    }
+
+    #[test]
+    fn test_clk_ctrl() {
+        assert_eq!(Ok(0x0008), clk_ctrl(SampleRate::Hz48000, RateMode::Div1, Mclk::Fs256));
+        assert_eq!(Ok(0x0025), clk_ctrl(SampleRate::Hz44100, RateMode::Div4, Mclk::Fs384));
+        assert_eq!(Ok(0x000C), clk_ctrl(SampleRate::Hz96000, RateMode::Div1, Mclk::Fs256));
+        assert_eq!(Ok(0x000B), clk_ctrl(SampleRate::Hz48000, RateMode::Div1, Mclk::Pll(12_000_000)));
+        for &sys_fs in &SYS_FS[..3] {
+            for &mclk in &[Mclk::Fs256, Mclk::Fs384, Mclk::Fs512] {
+                assert!(clk_ctrl(sys_fs, RateMode::Div1, mclk).is_ok());
+            }
+        }
+        assert!(clk_ctrl(SampleRate::Hz96000, RateMode::Div1, Mclk::Fs384).is_err());
+        assert!(clk_ctrl(SampleRate::Hz96000, RateMode::Div2, Mclk::Fs512).is_err());
+        assert!(clk_ctrl(SampleRate::Hz48000, RateMode::Div1, Mclk::Pll(30_000_000)).is_err());
+    }
+
+    #[test]
+    fn test_from_mclk() {
+        fn from_mclk(mclk_hz: u32, sample_rate_hz: u32) -> (SampleRate, RateMode, Mclk) {
+            let clock = Clock::from_mclk(mclk_hz, sample_rate_hz).unwrap();
+            (clock.sys_fs(), clock.rate_mode(), clock.mclk())
+        }
+        assert_eq!((SampleRate::Hz48000, RateMode::Div1, Mclk::Fs256), from_mclk(12_288_000, 48_000));
+        assert_eq!((SampleRate::Hz48000, RateMode::Div1, Mclk::Fs384), from_mclk(18_432_000, 48_000));
+        assert_eq!((SampleRate::Hz48000, RateMode::Div1, Mclk::Fs512), from_mclk(24_576_000, 48_000));
+        assert_eq!((SampleRate::Hz96000, RateMode::Div1, Mclk::Fs256), from_mclk(24_576_000, 96_000));
+        assert_eq!((SampleRate::Hz44100, RateMode::Div1, Mclk::Fs256), from_mclk(11_289_600, 44_100));
+        assert_eq!((SampleRate::Hz32000, RateMode::Div4, Mclk::Fs384), from_mclk(12_288_000, 8_000));
+        assert_eq!((SampleRate::Hz48000, RateMode::Div2, Mclk::Fs512), from_mclk(24_576_000, 24_000));
+        // 96 kHz / 6 when 32 kHz / 2 needs the PLL
+        assert_eq!((SampleRate::Hz96000, RateMode::Div6, Mclk::Fs256), from_mclk(24_576_000, 16_000));
+        assert_eq!((SampleRate::Hz48000, RateMode::Div1, Mclk::Pll(12_000_000)), from_mclk(12_000_000, 48_000));
+        assert_eq!((SampleRate::Hz44100, RateMode::Div1, Mclk::Pll(12_288_000)), from_mclk(12_288_000, 44_100));
+
+        assert!(Clock::from_mclk(12_000_000, 50_000).is_err());
+        assert!(Clock::from_mclk(4_000_000, 48_000).is_err());
+        // Way beyond any SYS_FS, must not overflow
+        assert!(Clock::from_mclk(12_000_000, 800_000_000).is_err());
+        assert_eq!(None, sample_rate(u32::MAX));
+    }
+
+    #[test]
+    fn test_error_ppm() {
+        let clock = Clock::from_mclk(12_288_000, 48_000).unwrap();
+        assert_eq!(48_000.0, clock.exact_sample_rate());
+        assert_eq!(0.0, clock.error_ppm());
+
+        // 12 MHz * 16.3837890625 = 196.60546875 MHz
+        let clock = Clock::from_mclk(12_000_000, 48_000).unwrap();
+        assert!((clock.exact_sample_rate() - 47_999.382).abs() < 0.001);
+        assert!((clock.error_ppm() + 12.87).abs() < 0.01);
+
+        // Exact through the PLL too
+        let clock = Clock::new(SampleRate::Hz48000, RateMode::Div2, Mclk::Pll(12_288_000)).unwrap();
+        assert_eq!(24_000.0, clock.exact_sample_rate());
+    }
+
+    fn divisors(input_freq_div2: bool, int_divisor: u8, frac_divisor: u16) -> Result<PllDivisors, &'static str> {
+        Ok(PllDivisors { input_freq_div2, int_divisor, frac_divisor })
+    }
+
+    #[test]
+    fn test_pll_divisors() {
+        // 196.608 MHz / 12 MHz = 16.384
+        assert_eq!(divisors(false, 16, 786), pll_divisors(12_000_000, SampleRate::Hz48000));
+        assert_eq!(divisors(false, 16, 0), pll_divisors(12_288_000, SampleRate::Hz48000));
+        assert_eq!(divisors(false, 15, 253), pll_divisors(13_000_000, SampleRate::Hz48000));
+        // Halved to 12 MHz
+        assert_eq!(divisors(true, 16, 786), pll_divisors(24_000_000, SampleRate::Hz48000));
+        assert_eq!(divisors(true, 14, 1154), pll_divisors(27_000_000, SampleRate::Hz48000));
+
+        // 180.6336 MHz / 12 MHz = 15.0528
+        assert_eq!(divisors(false, 15, 108), pll_divisors(12_000_000, SampleRate::Hz44100));
+        assert_eq!(divisors(false, 14, 1433), pll_divisors(12_288_000, SampleRate::Hz44100));
+        assert_eq!(divisors(false, 13, 1832), pll_divisors(13_000_000, SampleRate::Hz44100));
+        assert_eq!(divisors(true, 15, 108), pll_divisors(24_000_000, SampleRate::Hz44100));
+        assert_eq!(divisors(true, 13, 778), pll_divisors(27_000_000, SampleRate::Hz44100));
+
+        // Same PLL output for 32 and 96 kHz
+        assert_eq!(pll_divisors(13_000_000, SampleRate::Hz48000), pll_divisors(13_000_000, SampleRate::Hz32000));
+        assert_eq!(pll_divisors(27_000_000, SampleRate::Hz48000), pll_divisors(27_000_000, SampleRate::Hz96000));
+
+        assert!(pll_divisors(6_000_000, SampleRate::Hz48000).is_err());
+        assert!(pll_divisors(28_000_000, SampleRate::Hz48000).is_err());
+    }
+}
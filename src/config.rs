@@ -0,0 +1,324 @@
+//! Codec settings applied at initialization
+//!
+//! [`Config::default()`] is what [`SGTL5000Control::new()`] sets up:
+//!
+//! - 3.3 V VDDA and VDDIO, VDDD from the internal regulator
+//! - 48 kHz from a 256·Fs MCLK, no PLL
+//! - codec-master 16-bit PCM format A at 32·Fs on I2S
+//! - I2S_IN → DAP → DAC playback on the headphone and line outputs
+//! - ADC and I2S_OUT powered down
+//!
+//! Use [`Config::builder()`] to change parts of it.
+//!
+//! [`SGTL5000Control::new()`]: crate::SGTL5000Control::new
+
+use crate::clock::{Clock, Mclk, PllLock};
+use crate::i2s::I2sFormat;
+use crate::registers::{DapSource, RateMode, SampleRate, Source};
+use crate::volume::Volume;
+
+/// Supply voltages in mV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Supply {
+    /// Analog supply
+    pub vdda: u16,
+    /// I/O supply
+    pub vddio: u16,
+    /// External digital core supply, `None` for the internal regulator
+    pub vddd: Option<u16>,
+}
+
+impl Default for Supply {
+    fn default() -> Self {
+        Supply {
+            vdda: 3300,
+            vddio: 3300,
+            vddd: None,
+        }
+    }
+}
+
+/// Digital audio routing, see CHIP_SSS_CTRL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Routing {
+    pub dac: Source,
+    pub dap: DapSource,
+    pub dap_mix: DapSource,
+    pub i2s_out: Source,
+    /// Play LINEIN on the headphones instead of the DAC output
+    pub headphone_line_in: bool,
+}
+
+impl Default for Routing {
+    /// I2S_IN → DAP → DAC
+    fn default() -> Self {
+        Routing {
+            dac: Source::Dap,
+            dap: DapSource::I2sIn,
+            dap_mix: DapSource::Adc,
+            i2s_out: Source::Adc,
+            headphone_line_in: false,
+        }
+    }
+}
+
+/// Blocks to power up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blocks {
+    pub adc: bool,
+    pub dac: bool,
+    pub dap: bool,
+    pub i2s_in: bool,
+    pub i2s_out: bool,
+    pub headphone: bool,
+    pub lineout: bool,
+}
+
+impl Default for Blocks {
+    /// Playback only
+    fn default() -> Self {
+        Blocks {
+            adc: false,
+            dac: true,
+            dap: true,
+            i2s_in: true,
+            i2s_out: false,
+            headphone: true,
+            lineout: true,
+        }
+    }
+}
+
+/// Initial volumes, see [`SGTL5000Control::set_dac_vol()`] and friends
+///
+/// [`SGTL5000Control::set_dac_vol()`]: crate::SGTL5000Control::set_dac_vol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volumes {
+    pub dac: Volume,
+    pub lineout: Volume,
+    pub headphone: Volume,
+}
+
+impl Default for Volumes {
+    /// DAC at 0 dB, line out and headphones about 0 dB
+    fn default() -> Self {
+        Volumes {
+            dac: Volume::from(255),
+            lineout: Volume::from(206),
+            headphone: Volume::from(208),
+        }
+    }
+}
+
+/// Everything the codec is initialized with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub(crate) supply: Supply,
+    pub(crate) sys_fs: SampleRate,
+    pub(crate) rate_mode: RateMode,
+    pub(crate) mclk: Mclk,
+    pub(crate) pll_lock: PllLock,
+    pub(crate) i2s: I2sFormat,
+    pub(crate) routing: Routing,
+    pub(crate) blocks: Blocks,
+    pub(crate) volumes: Volumes,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            supply: Supply::default(),
+            sys_fs: SampleRate::Hz48000,
+            rate_mode: RateMode::Div1,
+            mclk: Mclk::Fs256,
+            pll_lock: PllLock::default(),
+            i2s: I2sFormat::default(),
+            routing: Routing::default(),
+            blocks: Blocks::default(),
+            volumes: Volumes::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Start from the defaults
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config::default(),
+        }
+    }
+
+    pub fn supply(&self) -> Supply {
+        self.supply
+    }
+
+    pub fn sys_fs(&self) -> SampleRate {
+        self.sys_fs
+    }
+
+    pub fn rate_mode(&self) -> RateMode {
+        self.rate_mode
+    }
+
+    pub fn mclk(&self) -> Mclk {
+        self.mclk
+    }
+
+    pub fn pll_lock(&self) -> PllLock {
+        self.pll_lock
+    }
+
+    pub fn i2s(&self) -> I2sFormat {
+        self.i2s
+    }
+
+    pub fn routing(&self) -> Routing {
+        self.routing
+    }
+
+    pub fn blocks(&self) -> Blocks {
+        self.blocks
+    }
+
+    pub fn volumes(&self) -> Volumes {
+        self.volumes
+    }
+
+    pub(crate) fn clock(&self) -> Result<Clock, &'static str> {
+        Clock::new(self.sys_fs, self.rate_mode, self.mclk)
+    }
+
+    /// Reject settings that cannot work together
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        let supply = &self.supply;
+        if !(1620..=3600).contains(&supply.vdda) || !(1620..=3600).contains(&supply.vddio) {
+            return Err("VDDA and VDDIO must be within 1.62..3.6V");
+        }
+        if let Some(vddd) = supply.vddd {
+            if !(1100..=2000).contains(&vddd) {
+                return Err("external VDDD must be within 1.1..2.0V");
+            }
+        }
+        self.clock()?;
+        let routing = &self.routing;
+        if (routing.dac == Source::Dap || routing.i2s_out == Source::Dap) && !self.blocks.dap {
+            return Err("DAP output routed but DAP not powered");
+        }
+        self.i2s.validate()
+    }
+}
+
+/// Builds a [`Config`] from the defaults
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn supply(mut self, supply: Supply) -> Self {
+        self.config.supply = supply;
+        self
+    }
+
+    /// Internal system sample rate SYS_FS
+    pub fn sys_fs(mut self, sys_fs: SampleRate) -> Self {
+        self.config.sys_fs = sys_fs;
+        self
+    }
+
+    /// Actual sample rate as a fraction of SYS_FS
+    pub fn rate_mode(mut self, rate_mode: RateMode) -> Self {
+        self.config.rate_mode = rate_mode;
+        self
+    }
+
+    /// SYS_MCLK input frequency relative to SYS_FS
+    pub fn mclk(mut self, mclk: Mclk) -> Self {
+        self.config.mclk = mclk;
+        self
+    }
+
+    /// How long to wait for the PLL to lock, with [`Mclk::Pll`] here
+    /// and in later clock changes
+    pub fn pll_lock(mut self, pll_lock: PllLock) -> Self {
+        self.config.pll_lock = pll_lock;
+        self
+    }
+
+    /// Digital audio interface format
+    pub fn i2s(mut self, i2s: I2sFormat) -> Self {
+        self.config.i2s = i2s;
+        self
+    }
+
+    pub fn routing(mut self, routing: Routing) -> Self {
+        self.config.routing = routing;
+        self
+    }
+
+    pub fn blocks(mut self, blocks: Blocks) -> Self {
+        self.config.blocks = blocks;
+        self
+    }
+
+    pub fn volumes(mut self, volumes: Volumes) -> Self {
+        self.config.volumes = volumes;
+        self
+    }
+
+    /// Settings are checked when initializing the codec
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::i2s::DataFormat;
+
+    #[test]
+    fn test_builder() {
+        let blocks = Blocks { adc: true, ..Blocks::default() };
+        let config = Config::builder()
+            .sys_fs(SampleRate::Hz44100)
+            .mclk(Mclk::Pll(12_000_000))
+            .blocks(blocks)
+            .build();
+        assert_eq!(SampleRate::Hz44100, config.sys_fs());
+        assert_eq!(RateMode::Div1, config.rate_mode());
+        assert_eq!(Mclk::Pll(12_000_000), config.mclk());
+        assert_eq!(10_000, config.pll_lock().timeout_us());
+        assert_eq!(blocks, config.blocks());
+        assert_eq!(Supply::default(), config.supply());
+        assert_eq!(I2sFormat::default(), config.i2s());
+        assert_eq!(Routing::default(), config.routing());
+        assert_eq!(Volumes::default(), config.volumes());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), Config::default().validate());
+
+        let mut i2s = I2sFormat::for_sample::<i32>();
+        assert_eq!(Ok(()), Config::builder().i2s(i2s).build().validate());
+        i2s.format = DataFormat::RightJustified;
+        assert!(Config::builder().i2s(i2s).build().validate().is_err());
+
+        let config = Config::builder()
+            .sys_fs(SampleRate::Hz96000)
+            .mclk(Mclk::Fs512)
+            .build();
+        assert!(config.validate().is_err());
+
+        let supply = Supply { vdda: 5000, ..Supply::default() };
+        assert!(Config::builder().supply(supply).build().validate().is_err());
+        let supply = Supply { vddd: Some(1000), ..Supply::default() };
+        assert!(Config::builder().supply(supply).build().validate().is_err());
+
+        let blocks = Blocks { dap: false, ..Blocks::default() };
+        assert!(Config::builder().blocks(blocks).build().validate().is_err());
+        let routing = Routing { dac: Source::I2sIn, ..Routing::default() };
+        assert_eq!(Ok(()), Config::builder().blocks(blocks).routing(routing).build().validate());
+    }
+}
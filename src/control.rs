@@ -1,298 +1,582 @@
-use byteorder::{ByteOrder, BigEndian};
+use core::fmt;
 
-use embedded_hal::blocking::i2c;
-use stm32f429_hal::i2c::{I2c, Error as I2cError};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+use embedded_hal::spi::SpiDevice;
 
-use registers::*;
-use error::Error;
-use volume::Volume;
+use crate::clock::{Clock, Mclk, PllLock};
+use crate::config::Config;
+use crate::i2s::I2sFormat;
+use crate::registers::*;
+use crate::error::Error;
+use crate::volume::Volume;
+use crate::sequence::{self, Step};
+use crate::interface::{Address, I2cInterface, RegisterInterface, SpiInterface};
 
 
-pub struct SGTL5000Control<I2C: i2c::Read + i2c::Write> {
-    i2c: I2C,
+/// For the default [`Config`], which does not use the PLL
+pub(crate) struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl embedded_hal_async::delay::DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+pub struct SGTL5000Control<IF: RegisterInterface> {
+    interface: IF,
+    /// Read back every register write
+    verify: bool,
+    pll_lock: PllLock,
+}
+
+impl<I2C: I2c> SGTL5000Control<I2cInterface<I2C>> {
+    pub fn new(i2c: I2C, address: Address) -> Result<Self, Error<I2C::Error>> {
+        Self::with_interface(I2cInterface::new(i2c, address))
+    }
+
+    /// Like `new()`, but with custom settings, see
+    /// [`with_interface_config()`](Self::with_interface_config)
+    pub fn with_config<D: DelayNs>(i2c: I2C, address: Address, config: &Config, delay: &mut D) -> Result<Self, Error<I2C::Error>> {
+        Self::with_interface_config(I2cInterface::new(i2c, address), config, delay)
+    }
+
+    /// Like `new()`, but with [verification](Self::set_verify) already
+    /// active during initialization
+    pub fn new_verified(i2c: I2C, address: Address) -> Result<Self, Error<I2C::Error>> {
+        Self::init_with(I2cInterface::new(i2c, address), &Config::default(), true, &mut NoDelay)
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> I2C {
+        self.interface.release()
+    }
+}
+
+impl<SPI: SpiDevice> SGTL5000Control<SpiInterface<SPI>> {
+    /// The codec must be freshly reset because registers cannot be
+    /// read in SPI mode, see [`SpiInterface`].
+    pub fn new_spi(spi: SPI) -> Result<Self, Error<SPI::Error>> {
+        Self::with_interface(SpiInterface::new(spi))
+    }
+
+    /// Like `new_spi()`, but with custom settings, see
+    /// [`with_interface_config()`](Self::with_interface_config)
+    pub fn new_spi_with_config<D: DelayNs>(spi: SPI, config: &Config, delay: &mut D) -> Result<Self, Error<SPI::Error>> {
+        Self::with_interface_config(SpiInterface::new(spi), config, delay)
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> SPI {
+        self.interface.release()
+    }
 }
 
-const I2C_ADDR: u8 = 0b1010;
-
-impl<I2C: i2c::Read<Error=I2CE> + i2c::Write<Error=I2CE>, I2CE> SGTL5000Control<I2C> {
-    // TODO: -pub
-    pub(crate) fn read_register<R: I2cRegister>(&mut self) -> Result<R, I2CE> {
-        let addr = R::register_addr();
-        // Send register addr
-        let mut addr_buf = [0u8; 2];
-        BigEndian::write_u16(&mut addr_buf, addr);
-        self.i2c.write(I2C_ADDR, &addr_buf)?;
-
-        // Receive value
-        let mut value_buf = [0u8; 2];
-        self.i2c.read(I2C_ADDR, &mut value_buf)?;
-        let value = BigEndian::read_u16(&value_buf);
+impl<IF: RegisterInterface<Error=E>, E> SGTL5000Control<IF> {
+    pub fn read_register<R: I2cRegister>(&mut self) -> Result<R, Error<E>> {
+        let value = self.interface.read_register(R::register_addr())?;
         Ok(R::new(value))
     }
 
-    fn write_register<R: I2cRegister>(&mut self, register: R) -> Result<(), I2CE> {
-        let addr = R::register_addr();
-        let value = register.to_inner();
-        // Send register addr and value
-        let mut buf = [0u8; 4];
-        BigEndian::write_u16(&mut buf[0..2], addr);
-        BigEndian::write_u16(&mut buf[2..4], value);
-        self.i2c.write(I2C_ADDR, &buf)?;
+    pub fn write_register<R: I2cRegister>(&mut self, register: R) -> Result<(), Error<E>> {
+        self.write_raw(R::register_addr(), register.to_inner())
+    }
+
+    fn write_raw(&mut self, addr: u16, value: u16) -> Result<(), Error<E>> {
+        self.interface.write_register(addr, value)?;
 
+        if self.verify {
+            let read = self.interface.read_register(addr)?;
+            if (read ^ value) & writable_mask(addr) != 0 {
+                return Err(Error::VerifyMismatch { addr, wrote: value, read });
+            }
+        }
         Ok(())
     }
 
-    fn modify_register<R, F>(&mut self, f: F) -> Result<(), I2CE>
-    where R: I2cRegister,
-          F: Fn(R) -> R
-    {
-        let register: R = self.read_register()?;
-        let register = f(register);
-        self.write_register(register)?;
+    /// Carry out one register update
+    fn run(&mut self, step: &Step) -> Result<(), Error<E>> {
+        let old = match *step {
+            Step::Write { .. } => 0,
+            Step::Modify { addr, .. } => self.interface.read_register(addr)?,
+        };
+        self.write_raw(step.addr(), step.apply(old))
+    }
 
-        Ok(())
+    /// Initialize the codec over any control port
+    pub fn with_interface(interface: IF) -> Result<Self, Error<E>> {
+        Self::with_interface_config(interface, &Config::default(), &mut NoDelay)
     }
 
-    pub fn new(i2c: I2C) -> Result<Self, Error<I2CE>> {
+    /// Initialize the codec with custom settings, failing with
+    /// [`Error::InvalidConfig`] before any register is written if they
+    /// do not fit together.
+    ///
+    /// With [`Mclk::Pll`], `delay` paces [`wait_pll_lock()`](Self::wait_pll_lock)
+    /// before the audio blocks are powered up.
+    pub fn with_interface_config<D: DelayNs>(interface: IF, config: &Config, delay: &mut D) -> Result<Self, Error<E>> {
+        Self::init_with(interface, config, false, delay)
+    }
+
+    fn init_with<D: DelayNs>(interface: IF, config: &Config, verify: bool, delay: &mut D) -> Result<Self, Error<E>> {
+        config.validate().map_err(Error::InvalidConfig)?;
         let mut sgtl5000 = Self {
-            i2c,
+            interface,
+            verify,
+            pll_lock: config.pll_lock,
         };
-        sgtl5000.init()?;
+        sgtl5000.init(config, delay)?;
         Ok(sgtl5000)
     }
 
-    /// 0.8V
-    const ANA_GND_BASE: u16 = 800;
-    /// 0.025V
-    const ANA_GND_STEP: u16 = 25;
-    
-    fn init(&mut self) -> Result<(), Error<I2CE>> {
+    /// Read back every register after writing it, failing with
+    /// [`Error::VerifyMismatch`] if the writable bits differ.
+    ///
+    /// Only meaningful over I2C without [cache](crate::cache),
+    /// otherwise the read back value is the one just written.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Access the control port, e.g. to [`sync()`](crate::cache::Cached::sync)
+    /// a register cache
+    pub fn interface_mut(&mut self) -> &mut IF {
+        &mut self.interface
+    }
+
+    /// Give back the control port
+    pub fn release_interface(self) -> IF {
+        self.interface
+    }
+
+    fn init<D: DelayNs>(&mut self, config: &Config, delay: &mut D) -> Result<(), Error<E>> {
         let chip_id: ChipId = self.read_register()?;
         if chip_id.partid() != 0xA0 {
             return Err(Error::Identification)
         }
-        
-        self.modify_register(|mut ana_power: ChipAnaPower| {
-            ana_power.set_reftop_powerup(true);
-            // Enable stereo
-            ana_power.set_dac_mono(true);
-            // ana_power.set_adc_mono(true);
-
-            ana_power
-        })?;
-        self.modify_register(|mut linreg: ChipLinregCtrl| {
-            // VDDA & VDDIO both over 3.1V
-            linreg.set_vdcc_assn_ovrd(false);
-            linreg.set_vdcc_man_assn(false);
-            linreg.set_d_programming(0);
-            linreg
-        })?;
-        // // Setup PL for 12 MHz clock
-        // self.modify_register(|mut clk_top_ctrl: ChipClkTopCtrl| {
-        //     clk_top_ctrl.set_input_freq_div2(false);
-        //     clk_top_ctrl
-        // })?;
-        // self.modify_register(|mut pll_ctrl: ChipPllCtrl| {
-        //     pll_ctrl.set_int_divisor(16);
-        //     pll_ctrl.set_frac_divisor(786);
-        //     pll_ctrl
-        // })?;
-
-        // 3.3V
-        let vdda = 3300;
-        // 0.8..1575V
-        let vag: u16 = vdda / 2;
-        let vag_val = vag.saturating_sub(Self::ANA_GND_BASE) / Self::ANA_GND_STEP;
-        self.modify_register(|mut ref_ctrl: ChipRefCtrl| {
-            ref_ctrl.set_vag_val(vag_val.min(0x1F) as u8);
-            ref_ctrl.set_bias_ctrl(1);
-            ref_ctrl
-        })?;
-        self.modify_register(|mut line_out_ctrl: ChipLineOutCtrl| {
-            // LO_VAGCNTRL=1.65V
-            line_out_ctrl.set_lo_vagcntrl(vag_val.min(0x23) as u8);
-            // OUT_CURRENT=0.54mA
-            line_out_ctrl.set_out_current(0xF);
-            line_out_ctrl
-        })?;
-        self.modify_register(|mut short_ctrl: ChipShortCtrl| {
-            short_ctrl.set_lvladjr(4);
-            short_ctrl.set_lvladjl(4);
-            short_ctrl.set_lvladjc(4);
-            short_ctrl.set_mode_lr(1);
-            short_ctrl.set_mode_cm(2);
-            short_ctrl
-        })?;
-        self.modify_register(|mut ana_ctrl: ChipAnaCtrl| {
-            // Select DAC as the input to HP_OUT
-            ana_ctrl.set_select_hp(false);
-            // Unmute
-            ana_ctrl.set_mute_hp(false);
-            ana_ctrl.set_mute_lo(false);
-            // ana_ctrl.set_mute_adc(false);
-            ana_ctrl.set_en_zcd_hp(true);
-            ana_ctrl
-        })?;
-
-        self.modify_register(|mut ana_power: ChipAnaPower| {
-            // Power up internal linear regulator (Set bit 9)
-            ana_power.set_linreg_d_powerup(false);
-            ana_power.set_vddc_chrgpmp_powerup(true);
-
-            ana_power.set_pll_powerup(false);
-            ana_power.set_vcoamp_powerup(true);
-
-            // Enable stereo
-            ana_power.set_dac_mono(true);
-
-            ana_power
-        })?;
-        let mut clk_ctrl = ChipClkCtrl::new(0);
-        // Configure SYS_FS clock to 48 kHz
-        clk_ctrl.set_sys_fs(2);
-        // Configure MCLK_FREQ to 256*Fs
-        clk_ctrl.set_mclk_freq(0);
-        // // Use PLL
-        // clk_ctrl.set_mclk_freq(3);
-        // 1/1
-        clk_ctrl.set_rate_mode(0);
-        self.write_register(clk_ctrl)?;
-        self.modify_register(|mut i2s_ctrl: ChipI2sCtrl| {
-            // Master mode
-            i2s_ctrl.set_ms(true);
-            // 32Fs
-            i2s_ctrl.set_sclkfreq(true);
-            i2s_ctrl.set_sclk_inv(false);
-            i2s_ctrl.set_lralign(false);
-            i2s_ctrl.set_lrpol(false);
-            // i2s_ctrl.set_pcmsync(false);
-            // I2S data length: 0: 32 bits, 3: 16 bits
-            // TODO: let depend on types
-            i2s_ctrl.set_dlen(3);
-            // PCM standard
-            i2s_ctrl.set_i2s_mode(2);
+
+        let clock = config.clock().map_err(Error::InvalidConfig)?;
+        for step in sequence::power_up(config, &clock).map_err(Error::OutOfRange)?.steps() {
+            self.run(step)?;
+        }
+        if clock.pll().is_some() {
+            self.wait_pll_lock(delay)?;
+        }
+        for step in sequence::enable_blocks(config).map_err(Error::OutOfRange)?.steps() {
+            self.run(step)?;
+        }
+        Ok(())
+    }
+
+    /// Set DAC volume
+    pub fn set_dac_vol<V: Into<Volume>>(&mut self, v: V) -> Result<(), Error<E>> {
+        let step = sequence::dac_vol(v.into()).map_err(Error::OutOfRange)?;
+        self.run(&step)
+    }
+
+    /// Set LINE_OUT volume
+    pub fn set_lineout_vol<V: Into<Volume>>(&mut self, v: V) -> Result<(), Error<E>> {
+        let step = sequence::lineout_vol(v.into()).map_err(Error::OutOfRange)?;
+        self.run(&step)
+    }
+
+    /// Set headphones volume
+    pub fn set_hp_vol<V: Into<Volume>>(&mut self, v: V) -> Result<(), Error<E>> {
+        let step = sequence::hp_vol(v.into()).map_err(Error::OutOfRange)?;
+        self.run(&step)
+    }
+
+    /// Switch to the sample rate `sys_fs` / `rate_mode` clocked from
+    /// `mclk`, failing with [`Error::InvalidConfig`] for combinations
+    /// the codec does not support. Waits for the PLL to lock if it is
+    /// used.
+    pub fn set_clock<D: DelayNs>(&mut self, sys_fs: SampleRate, rate_mode: RateMode, mclk: Mclk, delay: &mut D) -> Result<(), Error<E>> {
+        let clock = Clock::new(sys_fs, rate_mode, mclk).map_err(Error::InvalidConfig)?;
+        for step in sequence::set_clock(&clock).map_err(Error::OutOfRange)?.steps() {
+            self.run(step)?;
+        }
+        if clock.pll().is_some() {
+            self.wait_pll_lock(delay)?;
+        }
+        Ok(())
+    }
+
+    /// Poll CHIP_ANA_STATUS until the PLL is locked, failing with
+    /// [`Error::PllNotLocked`] after the [`PllLock`] timeout from the
+    /// [`Config`]
+    ///
+    /// If the control port [cannot read](RegisterInterface::can_read)
+    /// the status, just wait the full timeout.
+    pub fn wait_pll_lock<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Error<E>> {
+        let pll_lock = self.pll_lock;
+        if !self.interface.can_read() {
+            delay.delay_us(pll_lock.timeout_us());
+            return Ok(());
+        }
+        for _ in 0..pll_lock.polls {
+            if self.pll_is_locked()? {
+                return Ok(());
+            }
+            delay.delay_us(pll_lock.interval_us);
+        }
+        // The PLL may have locked during the last interval
+        if self.pll_is_locked()? {
+            return Ok(());
+        }
+        Err(Error::PllNotLocked)
+    }
+
+    fn pll_is_locked(&mut self) -> Result<bool, Error<E>> {
+        let ana_status: ChipAnaStatus = self.read_register()?;
+        Ok(ana_status.pll_is_locked())
+    }
+
+    /// Switch to `sample_rate_hz` with SYS_MCLK running at `mclk_hz`,
+    /// through the PLL if needed. The returned [`Clock`] tells the
+    /// exact sample rate.
+    pub fn set_sample_rate<D: DelayNs>(&mut self, mclk_hz: u32, sample_rate_hz: u32, delay: &mut D) -> Result<Clock, Error<E>> {
+        let clock = Clock::from_mclk(mclk_hz, sample_rate_hz).map_err(Error::InvalidConfig)?;
+        self.set_clock(clock.sys_fs(), clock.rate_mode(), clock.mclk(), delay)?;
+        Ok(clock)
+    }
+
+    /// Change the digital audio interface format, failing with
+    /// [`Error::InvalidConfig`] for combinations the codec cannot
+    /// transfer
+    pub fn set_i2s_format(&mut self, format: &I2sFormat) -> Result<(), Error<E>> {
+        format.validate().map_err(Error::InvalidConfig)?;
+        self.run(&Step::modify(|mut i2s_ctrl: ChipI2sCtrl| {
+            format.apply(&mut i2s_ctrl);
             i2s_ctrl
-        })?;
-        self.modify_register(|mut ana_power: ChipAnaPower| {
-            ana_power.set_startup_powerup(false);
-            ana_power.set_linreg_simple_powerup(true);
-
-            // Power up desired digital blocks
-            ana_power.set_lineout_powerup(true);
-            ana_power.set_adc_powerup(true);
-            ana_power.set_capless_headphone_powerup(true);
-            ana_power.set_dac_powerup(true);
-            ana_power.set_headphone_powerup(true);
-            ana_power.set_reftop_powerup(true);
-
-            ana_power.set_vag_powerup(true);
-            ana_power
-        })?;
-        self.modify_register(|mut dap_control: DapControl| {
-            dap_control.set_dap_en(true);
+        }))
+    }
+
+    /// Print every register with its decoded fields
+    pub fn dump_registers<W: fmt::Write>(&mut self, w: &mut W) -> Result<(), Error<E>> {
+        for &(addr, _, _) in REGISTER_MAP.iter() {
+            let value = self.interface.read_register(addr)?;
+            write_register(w, addr, value).map_err(Error::Fmt)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use self::std::string::String;
+    use super::*;
+    use crate::config::{Blocks, Routing};
+    use crate::i2s::{DataFormat, I20, I24};
+    use crate::sim::{CountingDelay, SimError, Simulator};
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    #[test]
+    fn test_new() {
+        let control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        let sim = control.release();
+        // DAC, DAP and I2S_IN powered up
+        assert_eq!(Ok(0x0031), sim.peek(ChipDigPower::register_addr()));
+        // 48 kHz, 256*Fs
+        assert_eq!(Ok(0x0008), sim.peek(ChipClkCtrl::register_addr()));
+        // I2S_IN -> DAP -> DAC
+        assert_eq!(Ok(0x0070), sim.peek(ChipSssCtrl::register_addr()));
+        assert_eq!(Ok(0x3C3C), sim.peek(ChipDacVol::register_addr()));
+        assert_eq!(Ok(0x1919), sim.peek(ChipLineOutVol::register_addr()));
+        let ana_power = ChipAnaPower::new(sim.peek(ChipAnaPower::register_addr()).unwrap());
+        assert!(ana_power.dac_powerup());
+        assert!(ana_power.headphone_powerup());
+        assert!(ana_power.lineout_powerup());
+        assert!(!ana_power.startup_powerup());
+        // ADC off and muted
+        assert!(sim.register::<ChipAnaCtrl>().mute_adc());
+    }
+
+    #[test]
+    fn test_with_config() {
+        let mut i2s = I2sFormat::default();
+        i2s.master = false;
+        let config = Config::builder()
+            .sys_fs(SampleRate::Hz44100)
+            .mclk(Mclk::Fs512)
+            .i2s(i2s)
+            .blocks(Blocks { adc: true, i2s_out: true, ..Blocks::default() })
+            .routing(Routing { i2s_out: Source::Adc, ..Routing::default() })
+            .build();
+        let control = SGTL5000Control::with_config(Simulator::new(), Address::Adr0Low, &config, &mut CountingDelay(0)).unwrap();
+        let sim = control.release();
+        assert_eq!(Ok(0x0006), sim.peek(ChipClkCtrl::register_addr()));
+        assert!(!sim.register::<ChipI2sCtrl>().ms());
+        assert_eq!(Ok(0x0073), sim.peek(ChipDigPower::register_addr()));
+        assert!(sim.register::<ChipAnaPower>().adc_powerup());
+        // Capturing, so the ADC must not be muted
+        let ana_ctrl = sim.register::<ChipAnaCtrl>();
+        assert!(!ana_ctrl.mute_adc());
+        assert_eq!(Ok(0x0020), sim.peek(ChipAnaCtrl::register_addr()));
+
+        let mut i2s = I2sFormat::for_sample::<i32>();
+        i2s.format = DataFormat::RightJustified;
+        let config = Config::builder()
+            .i2s(i2s)
+            .build();
+        let sim = Simulator::new();
+        match SGTL5000Control::with_config(sim, Address::Adr0Low, &config, &mut CountingDelay(0)) {
+            Err(Error::InvalidConfig(_)) => {}
+            _ => panic!("SGTL5000Control::with_config() should fail"),
+        }
+    }
+
+    #[test]
+    fn test_set_clock() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        control.set_clock(SampleRate::Hz32000, RateMode::Div4, Mclk::Fs512, &mut CountingDelay(0)).unwrap();
+        match control.set_clock(SampleRate::Hz96000, RateMode::Div1, Mclk::Fs384, &mut CountingDelay(0)) {
+            Err(Error::InvalidConfig(_)) => {}
+            result => panic!("Unexpected {:?}", result),
+        }
+        let sim = control.release();
+        // 8 kHz
+        assert_eq!(Ok(0x0022), sim.peek(ChipClkCtrl::register_addr()));
+
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        control.set_clock(SampleRate::Hz44100, RateMode::Div1, Mclk::Pll(12_000_000), &mut CountingDelay(0)).unwrap();
+        let sim = control.release();
+        assert_eq!(Ok(0x0007), sim.peek(ChipClkCtrl::register_addr()));
+        assert_eq!(15, sim.register::<ChipPllCtrl>().int_divisor());
+        assert!(sim.register::<ChipAnaPower>().pll_powerup());
+    }
+
+    #[test]
+    fn test_wait_pll_lock() {
+        let config = Config::builder()
+            .mclk(Mclk::Pll(12_000_000))
+            .build();
+        let interface = I2cInterface::new(Simulator::new(), Address::Adr0Low);
+        let mut delay = CountingDelay(0);
+        let mut control = SGTL5000Control::with_interface_config(interface, &config, &mut delay).unwrap();
+        control.wait_pll_lock(&mut delay).unwrap();
+        assert_eq!(0, delay.0);
+
+        // Initialization stops before powering up the audio blocks
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(None);
+        let interface = I2cInterface::new(sim, Address::Adr0Low);
+        match SGTL5000Control::with_interface_config(interface, &config, &mut delay) {
+            Err(Error::PllNotLocked) => {}
+            result => panic!("Unexpected {:?}", result.map(|_| ())),
+        }
+        assert_eq!(10_000, delay.0);
+        delay.0 = 0;
+
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(None);
+        let mut control = SGTL5000Control::new(sim, Address::Adr0Low).unwrap();
+        match control.set_sample_rate(12_000_000, 48_000, &mut delay) {
+            Err(Error::PllNotLocked) => {}
+            result => panic!("Unexpected {:?}", result),
+        }
+        assert_eq!(10_000, delay.0);
+        // No PLL, no waiting
+        control.set_sample_rate(12_288_000, 48_000, &mut delay).unwrap();
+        assert_eq!(10_000, delay.0);
+    }
+
+    #[test]
+    fn test_pll_lock_timeout() {
+        let config = Config::builder()
+            .mclk(Mclk::Pll(12_000_000))
+            .pll_lock(PllLock { polls: 2, interval_us: 100 })
+            .build();
+        // Locked during the last interval
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(Some(2));
+        let mut delay = CountingDelay(0);
+        let control = SGTL5000Control::with_config(sim, Address::Adr0Low, &config, &mut delay).unwrap();
+        assert_eq!(200, delay.0);
+        assert!(control.release().register::<ChipAnaPower>().dac_powerup());
+
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(Some(3));
+        let mut delay = CountingDelay(0);
+        match SGTL5000Control::with_config(sim, Address::Adr0Low, &config, &mut delay) {
+            Err(Error::PllNotLocked) => {}
+            result => panic!("Unexpected {:?}", result.map(|_| ())),
+        }
+        assert_eq!(200, delay.0);
+    }
+
+    #[test]
+    fn test_set_sample_rate() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        let clock = control.set_sample_rate(12_000_000, 44_100, &mut CountingDelay(0)).unwrap();
+        assert_eq!(Mclk::Pll(12_000_000), clock.mclk());
+        assert!(clock.error_ppm().abs() < 50.0);
+        assert_eq!(Ok(MclkRatio::Pll), control.read_register::<ChipClkCtrl>().unwrap().mclk_freq());
+
+        let clock = control.set_sample_rate(12_288_000, 16_000, &mut CountingDelay(0)).unwrap();
+        assert_eq!(Mclk::Fs384, clock.mclk());
+        assert!(!control.read_register::<ChipAnaPower>().unwrap().pll_powerup());
+    }
+
+    #[test]
+    fn test_set_i2s_format() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        let mut format = I2sFormat::for_sample::<I24>();
+        format.format = DataFormat::I2s;
+        control.set_i2s_format(&format).unwrap();
+        let mut format = I2sFormat::for_sample::<i32>();
+        format.format = DataFormat::RightJustified;
+        match control.set_i2s_format(&format) {
+            Err(Error::InvalidConfig(_)) => {}
+            result => panic!("Unexpected {:?}", result),
+        }
+        assert_eq!(0x0090, control.read_register::<ChipI2sCtrl>().unwrap().to_inner());
+        let mut format = I2sFormat::for_sample::<I20>();
+        format.format = DataFormat::RightJustified;
+        control.set_i2s_format(&format).unwrap();
+        let sim = control.release();
+        assert_eq!(Ok(0x00A4), sim.peek(ChipI2sCtrl::register_addr()));
+    }
+
+    #[test]
+    fn test_new_identification() {
+        let mut sim = Simulator::new();
+        sim.poke(ChipId::register_addr(), 0x1234).unwrap();
+        match SGTL5000Control::new(sim, Address::Adr0Low) {
+            Err(Error::Identification) => {}
+            _ => panic!("SGTL5000Control::new() should fail"),
+        }
+    }
+
+    #[test]
+    fn test_modify_step() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        control.run(&Step::modify(|mut dap_control: DapControl| {
+            dap_control.set_mix_en(true);
             dap_control
-        })?;
-
-        // Power up desired digital blocks
-        self.modify_register(|mut dig_power: ChipDigPower| {
-            // dig_power.set_adc_powerup(true);
-            dig_power.set_dac_powerup(true);
-            dig_power.set_dap_powerup(true);
-            // dig_power.set_i2s_out_powerup(true);
-            dig_power.set_i2s_in_powerup(true);
-            dig_power
-        })?;
-
-        // Setup routing
-        // Example 1: I2S_IN -> DAP -> DAC -> LINEOUT, HP_OUT
-        self.modify_register(|mut sss_ctrl: ChipSssCtrl| {
-            // Route I2S_IN to DAP
-            sss_ctrl.set_dap_select(1);
-            // Route DAP to DAC
-            sss_ctrl.set_dac_select(3);
-            sss_ctrl
-        })?;
-        self.modify_register(|mut adcdac_ctrl: ChipAdcdacCtrl| {
-            adcdac_ctrl.set_vol_ramp_en(true);
-            adcdac_ctrl.set_vol_expo_ramp(false);
-            adcdac_ctrl.set_dac_mute_right(false);
-            adcdac_ctrl.set_dac_mute_left(false);
-            adcdac_ctrl
-        })?;
-
-        // Volume
-        let mut ana_hp_ctrl = ChipAnaHpCtrl::new(0);
-        ana_hp_ctrl.set_hp_vol_right(0x18);
-        ana_hp_ctrl.set_hp_vol_left(0x18);
-        self.write_register(ana_hp_ctrl)?;
-
-        let mut line_out_vol = ChipLineOutVol::new(0);
-        line_out_vol.set_lo_vol_right(0x19);
-        line_out_vol.set_lo_vol_left(0x19);
-        self.write_register(line_out_vol)?;
-
-        let mut dac_vol = ChipDacVol::new(0);
-        // Min: 0xFC, Max: 0x3c
-        dac_vol.set_dac_vol_right(0x3c);
-        dac_vol.set_dac_vol_left(0x3c);
-        self.write_register(dac_vol)?;
-        // self.set_dac_vol(0xff);
-        // self.set_lineout_vol(0xff);
-        // self.set_hp_vol(0xff);
-
-                             // if false {
-        use core::fmt::Write;
-        use cortex_m_semihosting::hio;
-        let mut stdout = hio::hstdout().unwrap();
-        for a in 0x1B..0x1D /*0..0x20*/ {
-            let addr = 2 * a;
-            // Send register addr
-            let mut addr_buf = [0u8; 2];
-            BigEndian::write_u16(&mut addr_buf, addr);
-            self.i2c.write(I2C_ADDR, &addr_buf)?;
-
-            // Receive value
-            let mut value_buf = [0u8; 2];
-            self.i2c.read(I2C_ADDR, &mut value_buf)?;
-            let value = BigEndian::read_u16(&value_buf);
-            
-            writeln!(stdout, "R {:02X} = {:04X}", addr, value).unwrap();
+        })).unwrap();
+        let dap_control: DapControl = control.read_register().unwrap();
+        assert!(dap_control.mix_en());
+        assert!(dap_control.dap_en());
+    }
+
+    #[test]
+    fn test_set_vol() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        control.set_dac_vol(0).unwrap();
+        control.set_lineout_vol((0, 255)).unwrap();
+        control.set_hp_vol(255).unwrap();
+        let sim = control.release();
+        assert_eq!(Ok(0xFCFC), sim.peek(ChipDacVol::register_addr()));
+        assert_eq!(Ok(0x1F00), sim.peek(ChipLineOutVol::register_addr()));
+        assert_eq!(Ok(0x0000), sim.peek(ChipAnaHpCtrl::register_addr()));
+
+        // Left stays left
+        let mut control = SGTL5000Control::new(sim, Address::Adr0Low).unwrap();
+        control.set_hp_vol((0, 255)).unwrap();
+        let ana_hp_ctrl: ChipAnaHpCtrl = control.read_register().unwrap();
+        assert_eq!((0x7F, 0x00), (ana_hp_ctrl.hp_vol_left(), ana_hp_ctrl.hp_vol_right()));
+    }
+
+    #[test]
+    fn test_dump_registers() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        let mut dump = String::new();
+        control.dump_registers(&mut dump).unwrap();
+
+        let mut lines = dump.lines();
+        assert_eq!(Some("0000 A011 CHIP_ID"), lines.next());
+        assert_eq!(Some("    partid: 0xa0"), lines.next());
+        assert_eq!(Some("    revid: 0x11"), lines.next());
+        assert_eq!(Some("0002 0031 CHIP_DIG_POWER"), lines.next());
+        assert!(dump.contains("0004 0008 CHIP_CLK_CTRL\n    rate_mode: Div1\n    sys_fs: Hz48000\n    mclk_freq: Fs256\n"));
+        assert!(dump.contains("    dap_select: I2sIn\n    dac_select: Dap\n"));
+        assert_eq!(REGISTER_MAP.len(), dump.lines().filter(|line| !line.starts_with(' ')).count());
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(0x0A, Address::Adr0Low.i2c_addr());
+        assert_eq!(0x2A, Address::Adr0High.i2c_addr());
+
+        let sim = Simulator::with_address(Address::Adr0High);
+        match SGTL5000Control::new(sim, Address::Adr0Low) {
+            Err(Error::I2cError(SimError::AddressNack(0x0A))) => {}
+            _ => panic!("SGTL5000Control::new() should fail"),
         }
-                             // }
+    }
 
-        Ok(())
+    /// Two codecs sharing one bus
+    struct Bus {
+        low: Simulator,
+        high: Simulator,
     }
 
-    /// Set DAC volume
-    pub fn set_dac_vol<V: Into<Volume>>(&mut self, v: V) {
-        let volume = v.into();
-        let (left, right) = volume.to_range(0xFC, 0x3C);
+    impl ErrorType for Bus {
+        type Error = SimError;
+    }
 
-        let mut dac_vol = ChipDacVol::new(0);
-        dac_vol.set_dac_vol_left(left);
-        dac_vol.set_dac_vol_right(right);
-        self.write_register(dac_vol);
+    impl I2c for Bus {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), SimError> {
+            match self.low.transaction(address, operations) {
+                Err(SimError::AddressNack(_)) => self.high.transaction(address, operations),
+                result => result,
+            }
+        }
     }
 
-    /// Set LINE_OUT volume
-    pub fn set_lineout_vol<V: Into<Volume>>(&mut self, v: V) {
-        let volume = v.into();
-        let (left, right) = volume.to_range(0, 0x1F);
+    #[test]
+    fn test_two_codecs() {
+        let bus = Bus {
+            low: Simulator::with_address(Address::Adr0Low),
+            high: Simulator::with_address(Address::Adr0High),
+        };
+        let mut control = SGTL5000Control::new(bus, Address::Adr0High).unwrap();
+        control.set_dac_vol(0).unwrap();
+        let bus = control.release();
+        assert_eq!(Ok(0xFCFC), bus.high.peek(ChipDacVol::register_addr()));
+        // Untouched
+        assert_eq!(Ok(0x0000), bus.low.peek(ChipDigPower::register_addr()));
+        assert_eq!(Ok(0x3C3C), bus.low.peek(ChipDacVol::register_addr()));
+    }
+
+    /// CHIP_LINE_OUT_VOL bit 8 does not stick
+    struct Flaky(Simulator);
 
-        let mut line_out_vol = ChipLineOutVol::new(0);
-        line_out_vol.set_lo_vol_left(left);
-        line_out_vol.set_lo_vol_right(right);
-        self.write_register(line_out_vol);
+    impl ErrorType for Flaky {
+        type Error = SimError;
     }
 
-    /// Set headphones volume
-    pub fn set_hp_vol<V: Into<Volume>>(&mut self, v: V) {
-        let volume = v.into();
-        let (left, right) = volume.to_range(0x7F, 0);
-
-        let mut ana_hp_ctrl = ChipAnaHpCtrl::new(0);
-        ana_hp_ctrl.set_hp_vol_right(left);
-        ana_hp_ctrl.set_hp_vol_left(right);
-        self.write_register(ana_hp_ctrl);
+    impl I2c for Flaky {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), SimError> {
+            self.0.transaction(address, operations)?;
+            let line_out_vol = self.0.peek(ChipLineOutVol::register_addr())?;
+            self.0.poke(ChipLineOutVol::register_addr(), line_out_vol & !0x0100)
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut control = SGTL5000Control::new(Simulator::new(), Address::Adr0Low).unwrap();
+        control.set_verify(true);
+        control.set_lineout_vol(255).unwrap();
+        // Read-only bits are ignored
+        control.run(&Step::Write { addr: ChipId::register_addr(), value: 0 }).unwrap();
+
+        let mut control = SGTL5000Control::new(Flaky(Simulator::new()), Address::Adr0Low).unwrap();
+        control.set_lineout_vol(255).unwrap();
+        control.set_verify(true);
+        match control.set_lineout_vol(255) {
+            Err(Error::VerifyMismatch { addr: 0x002E, wrote: 0x1F1F, read: 0x1E1F }) => {}
+            result => panic!("Unexpected {:?}", result),
+        }
+
+        match SGTL5000Control::new_verified(Flaky(Simulator::new()), Address::Adr0Low) {
+            Err(Error::VerifyMismatch { addr: 0x002E, wrote: 0x1919, read: 0x1819 }) => {}
+            _ => panic!("SGTL5000Control::new_verified() should fail"),
+        }
     }
 }
@@ -0,0 +1,309 @@
+use core::fmt;
+
+use byteorder::{ByteOrder, BigEndian};
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use crate::clock::{Clock, Mclk, PllLock};
+use crate::config::Config;
+use crate::i2s::I2sFormat;
+use crate::registers::*;
+use crate::error::Error;
+use crate::volume::Volume;
+use crate::sequence::{self, Step};
+use crate::interface::Address;
+use crate::control::NoDelay;
+
+/// Async counterpart of [`SGTL5000Control`](crate::SGTL5000Control)
+pub struct SGTL5000ControlAsync<I2C: I2c> {
+    i2c: I2C,
+    address: u8,
+    pll_lock: PllLock,
+}
+
+impl<I2C: I2c<Error=I2CE>, I2CE> SGTL5000ControlAsync<I2C> {
+    async fn read_raw(&mut self, addr: u16) -> Result<u16, I2CE> {
+        let mut addr_buf = [0u8; 2];
+        BigEndian::write_u16(&mut addr_buf, addr);
+
+        // Send register addr, then receive value after a repeated start
+        let mut value_buf = [0u8; 2];
+        self.i2c.write_read(self.address, &addr_buf, &mut value_buf).await?;
+        Ok(BigEndian::read_u16(&value_buf))
+    }
+
+    async fn write_raw(&mut self, addr: u16, value: u16) -> Result<(), I2CE> {
+        // Send register addr and value
+        let mut buf = [0u8; 4];
+        BigEndian::write_u16(&mut buf[0..2], addr);
+        BigEndian::write_u16(&mut buf[2..4], value);
+        self.i2c.write(self.address, &buf).await?;
+
+        Ok(())
+    }
+
+    pub async fn read_register<R: I2cRegister>(&mut self) -> Result<R, Error<I2CE>> {
+        let value = self.read_raw(R::register_addr()).await?;
+        Ok(R::new(value))
+    }
+
+    pub async fn write_register<R: I2cRegister>(&mut self, register: R) -> Result<(), Error<I2CE>> {
+        Ok(self.write_raw(R::register_addr(), register.to_inner()).await?)
+    }
+
+    /// Carry out one register update
+    async fn run(&mut self, step: &Step) -> Result<(), I2CE> {
+        let old = match *step {
+            Step::Write { .. } => 0,
+            Step::Modify { addr, .. } => self.read_raw(addr).await?,
+        };
+        self.write_raw(step.addr(), step.apply(old)).await
+    }
+
+    pub async fn new(i2c: I2C, address: Address) -> Result<Self, Error<I2CE>> {
+        Self::with_config(i2c, address, &Config::default(), &mut NoDelay).await
+    }
+
+    /// Like [`SGTL5000Control::with_config()`](crate::SGTL5000Control::with_config)
+    pub async fn with_config<D: DelayNs>(i2c: I2C, address: Address, config: &Config, delay: &mut D) -> Result<Self, Error<I2CE>> {
+        config.validate().map_err(Error::InvalidConfig)?;
+        let mut sgtl5000 = Self {
+            i2c,
+            address: address.i2c_addr(),
+            pll_lock: config.pll_lock,
+        };
+        sgtl5000.init(config, delay).await?;
+        Ok(sgtl5000)
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    async fn init<D: DelayNs>(&mut self, config: &Config, delay: &mut D) -> Result<(), Error<I2CE>> {
+        let chip_id: ChipId = self.read_register().await?;
+        if chip_id.partid() != 0xA0 {
+            return Err(Error::Identification)
+        }
+
+        let clock = config.clock().map_err(Error::InvalidConfig)?;
+        for step in sequence::power_up(config, &clock).map_err(Error::OutOfRange)?.steps() {
+            self.run(step).await?;
+        }
+        if clock.pll().is_some() {
+            self.wait_pll_lock(delay).await?;
+        }
+        for step in sequence::enable_blocks(config).map_err(Error::OutOfRange)?.steps() {
+            self.run(step).await?;
+        }
+        Ok(())
+    }
+
+    /// Set DAC volume
+    pub async fn set_dac_vol<V: Into<Volume>>(&mut self, v: V) -> Result<(), Error<I2CE>> {
+        let step = sequence::dac_vol(v.into()).map_err(Error::OutOfRange)?;
+        Ok(self.run(&step).await?)
+    }
+
+    /// Set LINE_OUT volume
+    pub async fn set_lineout_vol<V: Into<Volume>>(&mut self, v: V) -> Result<(), Error<I2CE>> {
+        let step = sequence::lineout_vol(v.into()).map_err(Error::OutOfRange)?;
+        Ok(self.run(&step).await?)
+    }
+
+    /// Set headphones volume
+    pub async fn set_hp_vol<V: Into<Volume>>(&mut self, v: V) -> Result<(), Error<I2CE>> {
+        let step = sequence::hp_vol(v.into()).map_err(Error::OutOfRange)?;
+        Ok(self.run(&step).await?)
+    }
+
+    /// Like [`SGTL5000Control::set_clock()`](crate::SGTL5000Control::set_clock)
+    pub async fn set_clock<D: DelayNs>(&mut self, sys_fs: SampleRate, rate_mode: RateMode, mclk: Mclk, delay: &mut D) -> Result<(), Error<I2CE>> {
+        let clock = Clock::new(sys_fs, rate_mode, mclk).map_err(Error::InvalidConfig)?;
+        for step in sequence::set_clock(&clock).map_err(Error::OutOfRange)?.steps() {
+            self.run(step).await?;
+        }
+        if clock.pll().is_some() {
+            self.wait_pll_lock(delay).await?;
+        }
+        Ok(())
+    }
+
+    /// Like [`SGTL5000Control::wait_pll_lock()`](crate::SGTL5000Control::wait_pll_lock)
+    pub async fn wait_pll_lock<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Error<I2CE>> {
+        let pll_lock = self.pll_lock;
+        for _ in 0..pll_lock.polls {
+            if self.pll_is_locked().await? {
+                return Ok(());
+            }
+            delay.delay_us(pll_lock.interval_us).await;
+        }
+        // The PLL may have locked during the last interval
+        if self.pll_is_locked().await? {
+            return Ok(());
+        }
+        Err(Error::PllNotLocked)
+    }
+
+    async fn pll_is_locked(&mut self) -> Result<bool, Error<I2CE>> {
+        let ana_status: ChipAnaStatus = self.read_register().await?;
+        Ok(ana_status.pll_is_locked())
+    }
+
+    /// Like [`SGTL5000Control::set_sample_rate()`](crate::SGTL5000Control::set_sample_rate)
+    pub async fn set_sample_rate<D: DelayNs>(&mut self, mclk_hz: u32, sample_rate_hz: u32, delay: &mut D) -> Result<Clock, Error<I2CE>> {
+        let clock = Clock::from_mclk(mclk_hz, sample_rate_hz).map_err(Error::InvalidConfig)?;
+        self.set_clock(clock.sys_fs(), clock.rate_mode(), clock.mclk(), delay).await?;
+        Ok(clock)
+    }
+
+    /// Like [`SGTL5000Control::set_i2s_format()`](crate::SGTL5000Control::set_i2s_format)
+    pub async fn set_i2s_format(&mut self, format: &I2sFormat) -> Result<(), Error<I2CE>> {
+        format.validate().map_err(Error::InvalidConfig)?;
+        let step = Step::modify(|mut i2s_ctrl: ChipI2sCtrl| {
+            format.apply(&mut i2s_ctrl);
+            i2s_ctrl
+        });
+        Ok(self.run(&step).await?)
+    }
+
+    /// Print every register with its decoded fields
+    pub async fn dump_registers<W: fmt::Write>(&mut self, w: &mut W) -> Result<(), Error<I2CE>> {
+        for &(addr, _, _) in REGISTER_MAP.iter() {
+            let value = self.read_raw(addr).await?;
+            write_register(w, addr, value).map_err(Error::Fmt)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+
+    use super::*;
+    use crate::sim::{CountingDelay, Simulator};
+
+    /// The simulator never blocks, so polling until ready is enough
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let control = block_on(SGTL5000ControlAsync::new(Simulator::new(), Address::Adr0Low)).unwrap();
+        let sim = control.release();
+        assert_eq!(Ok(0x0031), sim.peek(ChipDigPower::register_addr()));
+        assert_eq!(Ok(0x0070), sim.peek(ChipSssCtrl::register_addr()));
+        assert_eq!(Ok(0x1919), sim.peek(ChipLineOutVol::register_addr()));
+    }
+
+    #[test]
+    fn test_new_identification() {
+        let mut sim = Simulator::new();
+        sim.poke(ChipId::register_addr(), 0x1234).unwrap();
+        match block_on(SGTL5000ControlAsync::new(sim, Address::Adr0Low)) {
+            Err(Error::Identification) => {}
+            _ => panic!("SGTL5000ControlAsync::new() should fail"),
+        }
+    }
+
+    #[test]
+    fn test_set_vol() {
+        let mut control = block_on(SGTL5000ControlAsync::new(Simulator::new(), Address::Adr0Low)).unwrap();
+        block_on(control.set_dac_vol(0)).unwrap();
+        block_on(control.set_lineout_vol((0, 255))).unwrap();
+        block_on(control.set_hp_vol(255)).unwrap();
+        let sim = control.release();
+        assert_eq!(Ok(0xFCFC), sim.peek(ChipDacVol::register_addr()));
+        assert_eq!(Ok(0x1F00), sim.peek(ChipLineOutVol::register_addr()));
+        assert_eq!(Ok(0x0000), sim.peek(ChipAnaHpCtrl::register_addr()));
+    }
+
+    #[test]
+    fn test_with_config_pll() {
+        let config = Config::builder()
+            .mclk(Mclk::Pll(12_000_000))
+            .build();
+        let mut delay = CountingDelay(0);
+        let control = block_on(SGTL5000ControlAsync::with_config(Simulator::new(), Address::Adr0Low, &config, &mut delay)).unwrap();
+        assert_eq!(0, delay.0);
+        let sim = control.release();
+        assert_eq!(Ok(MclkRatio::Pll), sim.register::<ChipClkCtrl>().mclk_freq());
+        assert!(sim.register::<ChipAnaPower>().dac_powerup());
+
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(None);
+        match block_on(SGTL5000ControlAsync::with_config(sim, Address::Adr0Low, &config, &mut delay)) {
+            Err(Error::PllNotLocked) => {}
+            result => panic!("Unexpected {:?}", result.map(|_| ())),
+        }
+        assert_eq!(10_000, delay.0);
+    }
+
+    #[test]
+    fn test_pll_lock_timeout() {
+        let config = Config::builder()
+            .mclk(Mclk::Pll(12_000_000))
+            .pll_lock(PllLock { polls: 2, interval_us: 100 })
+            .build();
+        // Locked during the last interval
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(Some(2));
+        let mut delay = CountingDelay(0);
+        let control = block_on(SGTL5000ControlAsync::with_config(sim, Address::Adr0Low, &config, &mut delay)).unwrap();
+        assert_eq!(200, delay.0);
+        assert!(control.release().register::<ChipAnaPower>().dac_powerup());
+
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(Some(3));
+        let mut delay = CountingDelay(0);
+        match block_on(SGTL5000ControlAsync::with_config(sim, Address::Adr0Low, &config, &mut delay)) {
+            Err(Error::PllNotLocked) => {}
+            result => panic!("Unexpected {:?}", result.map(|_| ())),
+        }
+        assert_eq!(200, delay.0);
+    }
+
+    #[test]
+    fn test_set_clock() {
+        let mut control = block_on(SGTL5000ControlAsync::new(Simulator::new(), Address::Adr0Low)).unwrap();
+        let mut delay = CountingDelay(0);
+        block_on(control.set_clock(SampleRate::Hz44100, RateMode::Div1, Mclk::Pll(12_000_000), &mut delay)).unwrap();
+        assert_eq!(0, delay.0);
+        match block_on(control.set_clock(SampleRate::Hz96000, RateMode::Div1, Mclk::Fs384, &mut delay)) {
+            Err(Error::InvalidConfig(_)) => {}
+            result => panic!("Unexpected {:?}", result),
+        }
+        let sim = control.release();
+        assert_eq!(Ok(0x0007), sim.peek(ChipClkCtrl::register_addr()));
+        assert!(sim.register::<ChipAnaPower>().pll_powerup());
+    }
+
+    #[test]
+    fn test_wait_pll_lock() {
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(None);
+        let mut control = block_on(SGTL5000ControlAsync::new(sim, Address::Adr0Low)).unwrap();
+        let mut delay = CountingDelay(0);
+        match block_on(control.set_sample_rate(12_000_000, 48_000, &mut delay)) {
+            Err(Error::PllNotLocked) => {}
+            result => panic!("Unexpected {:?}", result),
+        }
+        assert_eq!(10_000, delay.0);
+        // No PLL, no waiting
+        block_on(control.set_sample_rate(12_288_000, 48_000, &mut delay)).unwrap();
+        assert_eq!(10_000, delay.0);
+    }
+}
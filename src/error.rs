@@ -1,9 +1,25 @@
+use core::fmt;
+
+use crate::registers::OutOfRange;
+
 #[derive(Debug)]
 pub enum Error<I2CE> {
-    /// I2c error passed up
+    /// Control port error passed up
     I2cError(I2CE),
     /// This is not an SGTL5000
     Identification,
+    /// Reading back a register after writing it returned different
+    /// writable bits
+    VerifyMismatch { addr: u16, wrote: u16, read: u16 },
+    /// A register field value does not fit or is reserved
+    OutOfRange(OutOfRange),
+    /// The requested settings cannot be combined or are not supported
+    /// by the codec
+    InvalidConfig(&'static str),
+    /// The PLL did not lock onto the input clock
+    PllNotLocked,
+    /// Writing a register dump to its sink failed
+    Fmt(fmt::Error),
 }
 
 impl<I2CE> From<I2CE> for Error<I2CE> {
@@ -11,3 +27,45 @@ impl<I2CE> From<I2CE> for Error<I2CE> {
         Error::I2cError(e)
     }
 }
+
+impl<I2CE: fmt::Debug> fmt::Display for Error<I2CE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::I2cError(e) =>
+                write!(f, "control port error: {:?}", e),
+            Error::Identification =>
+                write!(f, "no SGTL5000 found"),
+            Error::VerifyMismatch { addr, wrote, read } =>
+                write!(f, "register {:04X}: wrote {:04X}, read back {:04X}", addr, wrote, read),
+            Error::OutOfRange(e) =>
+                write!(f, "{}", e),
+            Error::InvalidConfig(reason) =>
+                write!(f, "invalid configuration: {}", reason),
+            Error::PllNotLocked =>
+                write!(f, "PLL not locked"),
+            Error::Fmt(_) =>
+                write!(f, "writing register dump failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I2CE: fmt::Debug> std::error::Error for Error<I2CE> {}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use self::std::string::ToString;
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e: Error<()> = Error::VerifyMismatch { addr: 0x0030, wrote: 0x7060, read: 0x7061 };
+        assert_eq!("register 0030: wrote 7060, read back 7061", e.to_string());
+        let e: Error<()> = Error::OutOfRange(OutOfRange { register: "CHIP_REF_CTRL", field: "set_vag_val", value: 0x20 });
+        assert_eq!("CHIP_REF_CTRL set_vag_val: 0x20 out of range", e.to_string());
+        let e: Error<()> = Error::InvalidConfig("24 bits need 64*Fs");
+        assert_eq!("invalid configuration: 24 bits need 64*Fs", e.to_string());
+    }
+}
@@ -0,0 +1,293 @@
+//! Digital audio interface format in CHIP_I2S_CTRL
+//!
+//! The word length and I2S_SCLK frequency follow from the [`Sample`]
+//! type, so streaming the same type that configured the codec keeps
+//! both ends of the I2S link agreeing:
+//!
+//! ```
+//! # use sgtl5000::{Config, I2sFormat};
+//! /// What the MCU side transfers
+//! type Frame = i16;
+//!
+//! let config = Config::builder()
+//!     .i2s(I2sFormat::for_sample::<Frame>())
+//!     .build();
+//! ```
+//!
+//! | Sample                     | DLEN | I2S_SCLK |
+//! |----------------------------|------|----------|
+//! | `i16`, `u16`               | 16   | 32·Fs    |
+//! | [`I16In32`]                | 16   | 64·Fs    |
+//! | [`I20`]                    | 20   | 64·Fs    |
+//! | [`I24`]                    | 24   | 64·Fs    |
+//! | `i32`                      | 32   | 64·Fs    |
+
+use crate::registers::{ChipI2sCtrl, I2sMode, WordLength};
+
+/// Frame layout on I2S_DIN and I2S_DOUT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Data one I2S_SCLK after the I2S_LRCLK edge
+    I2s,
+    /// Data right at the I2S_LRCLK edge
+    LeftJustified,
+    /// Data ending at the I2S_LRCLK edge
+    RightJustified,
+    /// PCM with data one I2S_SCLK after the frame sync
+    PcmA,
+    /// PCM with data right at the frame sync
+    PcmB,
+}
+
+impl DataFormat {
+    /// I2S_MODE and LRALIGN
+    fn mode(self) -> (I2sMode, bool) {
+        match self {
+            DataFormat::I2s => (I2sMode::I2sOrLeftJustified, false),
+            DataFormat::LeftJustified => (I2sMode::I2sOrLeftJustified, true),
+            DataFormat::RightJustified => (I2sMode::RightJustified, false),
+            DataFormat::PcmA => (I2sMode::Pcm, false),
+            DataFormat::PcmB => (I2sMode::Pcm, true),
+        }
+    }
+}
+
+/// I2S_SCLK frequency relative to the sample rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SclkRatio {
+    /// Room for two 16 bit words per frame
+    Fs32,
+    Fs64,
+}
+
+/// Everything about the I2S port the MCU side has to agree on
+///
+/// DLEN and SCLKFREQ are only set through [`for_sample()`](Self::for_sample).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2sFormat {
+    /// The codec drives I2S_SCLK and I2S_LRCLK
+    pub master: bool,
+    sclk: SclkRatio,
+    word_length: WordLength,
+    pub format: DataFormat,
+    /// Right channel while I2S_LRCLK is low, LRPOL
+    pub lrpol: bool,
+    /// Sample data on the falling I2S_SCLK edge, SCLK_INV
+    pub sclk_inv: bool,
+}
+
+mod private {
+    /// Keeps [`Sample`](super::Sample) to the word length and I2S_SCLK
+    /// pairs the codec can transfer
+    pub trait Sealed {}
+
+    impl Sealed for i16 {}
+    impl Sealed for u16 {}
+    impl Sealed for super::I16In32 {}
+    impl Sealed for super::I20 {}
+    impl Sealed for super::I24 {}
+    impl Sealed for i32 {}
+}
+
+/// A sample type streamed over I2S, which determines DLEN and the
+/// I2S_SCLK frequency
+///
+/// Implemented for every legal pair only, so a word length that does
+/// not fit into the I2S_SCLK frame cannot be configured:
+///
+/// ```compile_fail
+/// # use sgtl5000::{Sample, SclkRatio, WordLength};
+/// /// Claims more bits than 32·Fs carries
+/// struct Packed24;
+///
+/// impl Sample for Packed24 {
+///     const WORD_LENGTH: WordLength = WordLength::Bits24;
+///     const SCLK: SclkRatio = SclkRatio::Fs32;
+/// }
+/// ```
+pub trait Sample: private::Sealed {
+    const WORD_LENGTH: WordLength;
+    const SCLK: SclkRatio;
+}
+
+impl Sample for i16 {
+    const WORD_LENGTH: WordLength = WordLength::Bits16;
+    const SCLK: SclkRatio = SclkRatio::Fs32;
+}
+
+/// Raw 16 bit words, as many MCU I2S drivers transfer them
+impl Sample for u16 {
+    const WORD_LENGTH: WordLength = WordLength::Bits16;
+    const SCLK: SclkRatio = SclkRatio::Fs32;
+}
+
+/// 16 bit sample in the upper bits of a 32 bit word, for MCUs that
+/// only run I2S_SCLK at 64·Fs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct I16In32(pub i32);
+
+impl Sample for I16In32 {
+    const WORD_LENGTH: WordLength = WordLength::Bits16;
+    const SCLK: SclkRatio = SclkRatio::Fs64;
+}
+
+/// 20 bit sample in the upper bits of a 32 bit word
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct I20(pub i32);
+
+impl Sample for I20 {
+    const WORD_LENGTH: WordLength = WordLength::Bits20;
+    const SCLK: SclkRatio = SclkRatio::Fs64;
+}
+
+/// 24 bit sample in the upper bits of a 32 bit word
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct I24(pub i32);
+
+impl Sample for I24 {
+    const WORD_LENGTH: WordLength = WordLength::Bits24;
+    const SCLK: SclkRatio = SclkRatio::Fs64;
+}
+
+impl Sample for i32 {
+    const WORD_LENGTH: WordLength = WordLength::Bits32;
+    const SCLK: SclkRatio = SclkRatio::Fs64;
+}
+
+impl Default for I2sFormat {
+    /// Codec master, 16 bit PCM format A at 32·Fs, as for `i16`
+    fn default() -> Self {
+        I2sFormat {
+            master: true,
+            sclk: SclkRatio::Fs32,
+            word_length: WordLength::Bits16,
+            format: DataFormat::PcmA,
+            lrpol: false,
+            sclk_inv: false,
+        }
+    }
+}
+
+impl I2sFormat {
+    /// The default format with word length and I2S_SCLK for `S`
+    pub fn for_sample<S: Sample>() -> Self {
+        I2sFormat {
+            word_length: S::WORD_LENGTH,
+            sclk: S::SCLK,
+            ..I2sFormat::default()
+        }
+    }
+
+    pub fn word_length(&self) -> WordLength {
+        self.word_length
+    }
+
+    pub fn sclk(&self) -> SclkRatio {
+        self.sclk
+    }
+
+    /// Reject combinations the codec cannot transfer
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.sclk == SclkRatio::Fs32 && self.word_length != WordLength::Bits16 {
+            return Err("only 16 bit words fit into 32*Fs I2S_SCLK");
+        }
+        if self.format == DataFormat::RightJustified && self.word_length == WordLength::Bits32 {
+            return Err("right-justified data cannot be 32 bits");
+        }
+        Ok(())
+    }
+
+    /// Set the fields of CHIP_I2S_CTRL
+    pub fn apply(&self, i2s_ctrl: &mut ChipI2sCtrl) {
+        let (i2s_mode, lralign) = self.format.mode();
+        i2s_ctrl.set_ms(self.master);
+        i2s_ctrl.set_sclkfreq(self.sclk == SclkRatio::Fs32);
+        i2s_ctrl.set_sclk_inv(self.sclk_inv);
+        i2s_ctrl.set_dlen(self.word_length);
+        i2s_ctrl.set_i2s_mode(i2s_mode);
+        i2s_ctrl.set_lralign(lralign);
+        i2s_ctrl.set_lrpol(self.lrpol);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registers::I2cRegister;
+
+    fn i2s_ctrl(format: &I2sFormat) -> u16 {
+        let mut i2s_ctrl = ChipI2sCtrl::new(0);
+        format.apply(&mut i2s_ctrl);
+        i2s_ctrl.to_inner()
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(0x01B8, i2s_ctrl(&I2sFormat::default()));
+        let format = I2sFormat {
+            master: false,
+            sclk: SclkRatio::Fs64,
+            word_length: WordLength::Bits24,
+            format: DataFormat::LeftJustified,
+            lrpol: true,
+            sclk_inv: true,
+        };
+        assert_eq!(0x0053, i2s_ctrl(&format));
+        let format = I2sFormat { format: DataFormat::PcmB, ..format };
+        assert_eq!(0x005B, i2s_ctrl(&format));
+        let format = I2sFormat { format: DataFormat::RightJustified, word_length: WordLength::Bits20, ..format };
+        assert_eq!(0x0065, i2s_ctrl(&format));
+    }
+
+    #[test]
+    fn test_apply_for_sample() {
+        fn dlen_sclkfreq<S: Sample>() -> u16 {
+            let format = I2sFormat::for_sample::<S>();
+            assert_eq!(Ok(()), format.validate());
+            // SCLKFREQ and DLEN
+            i2s_ctrl(&format) & 0x0130
+        }
+        assert_eq!(0x0130, dlen_sclkfreq::<i16>());
+        assert_eq!(0x0130, dlen_sclkfreq::<u16>());
+        assert_eq!(0x0030, dlen_sclkfreq::<I16In32>());
+        assert_eq!(0x0020, dlen_sclkfreq::<I20>());
+        assert_eq!(0x0010, dlen_sclkfreq::<I24>());
+        assert_eq!(0x0000, dlen_sclkfreq::<i32>());
+    }
+
+    #[test]
+    fn test_for_sample() {
+        assert_eq!(I2sFormat::default(), I2sFormat::for_sample::<i16>());
+        assert_eq!(I2sFormat::default(), I2sFormat::for_sample::<u16>());
+        let format = I2sFormat::for_sample::<I16In32>();
+        assert_eq!((WordLength::Bits16, SclkRatio::Fs64), (format.word_length(), format.sclk()));
+        let format = I2sFormat::for_sample::<I20>();
+        assert_eq!((WordLength::Bits20, SclkRatio::Fs64), (format.word_length(), format.sclk()));
+        let format = I2sFormat::for_sample::<I24>();
+        assert_eq!((WordLength::Bits24, SclkRatio::Fs64), (format.word_length(), format.sclk()));
+        let format = I2sFormat::for_sample::<i32>();
+        assert_eq!((WordLength::Bits32, SclkRatio::Fs64), (format.word_length(), format.sclk()));
+        assert_eq!(Ok(()), format.validate());
+        // Not right-justified though
+        assert!(I2sFormat { format: DataFormat::RightJustified, ..format }.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), I2sFormat::default().validate());
+        for &word_length in &[WordLength::Bits20, WordLength::Bits24, WordLength::Bits32] {
+            let format = I2sFormat { word_length, ..I2sFormat::default() };
+            assert!(format.validate().is_err());
+            let format = I2sFormat { sclk: SclkRatio::Fs64, ..format };
+            assert_eq!(Ok(()), format.validate());
+        }
+
+        let format = I2sFormat {
+            sclk: SclkRatio::Fs64,
+            word_length: WordLength::Bits32,
+            format: DataFormat::RightJustified,
+            ..I2sFormat::default()
+        };
+        assert!(format.validate().is_err());
+    }
+}
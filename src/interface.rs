@@ -0,0 +1,253 @@
+//! Control port backends
+//!
+//! The SGTL5000 is controlled either over I2C or, with CTRL_MODE
+//! pulled high, over SPI. Both carry 16-bit register addresses and
+//! 16-bit values, MSB first.
+
+use byteorder::{ByteOrder, BigEndian};
+
+use embedded_hal::i2c::I2c;
+use embedded_hal::spi::SpiDevice;
+
+use crate::registers::{register_index, reset_values, written_value, REGISTER_MAP};
+
+/// Raw register access over a control port
+pub trait RegisterInterface {
+    type Error;
+
+    fn read_register(&mut self, addr: u16) -> Result<u16, Self::Error>;
+    fn write_register(&mut self, addr: u16, value: u16) -> Result<(), Self::Error>;
+
+    /// Whether `read_register()` gets the value from the codec, so
+    /// that status bits can be polled
+    fn can_read(&self) -> bool {
+        true
+    }
+}
+
+/// I2C bus address, selected by strapping the CTRL_ADR0_CS pin
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    /// CTRL_ADR0_CS pulled low: 0x0A
+    #[default]
+    Adr0Low,
+    /// CTRL_ADR0_CS pulled high: 0x2A
+    Adr0High,
+}
+
+impl Address {
+    /// 7-bit I2C address
+    pub fn i2c_addr(self) -> u8 {
+        match self {
+            Address::Adr0Low => 0b000_1010,
+            Address::Adr0High => 0b010_1010,
+        }
+    }
+}
+
+/// Control port in I2C mode (CTRL_MODE low)
+pub struct I2cInterface<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C: I2c> I2cInterface<I2C> {
+    pub fn new(i2c: I2C, address: Address) -> Self {
+        I2cInterface {
+            i2c,
+            address: address.i2c_addr(),
+        }
+    }
+
+    pub fn i2c(&self) -> &I2C {
+        &self.i2c
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: I2c> RegisterInterface for I2cInterface<I2C> {
+    type Error = I2C::Error;
+
+    fn read_register(&mut self, addr: u16) -> Result<u16, Self::Error> {
+        let mut addr_buf = [0u8; 2];
+        BigEndian::write_u16(&mut addr_buf, addr);
+
+        // Send register addr, then receive value after a repeated start
+        let mut value_buf = [0u8; 2];
+        self.i2c.write_read(self.address, &addr_buf, &mut value_buf)?;
+        Ok(BigEndian::read_u16(&value_buf))
+    }
+
+    fn write_register(&mut self, addr: u16, value: u16) -> Result<(), Self::Error> {
+        // Send register addr and value
+        let mut buf = [0u8; 4];
+        BigEndian::write_u16(&mut buf[0..2], addr);
+        BigEndian::write_u16(&mut buf[2..4], value);
+        self.i2c.write(self.address, &buf)?;
+
+        Ok(())
+    }
+}
+
+/// Control port in SPI mode (CTRL_MODE high)
+///
+/// CTRL_ADR0_CS is the chip select which the `SpiDevice` drives
+/// around every 32-bit frame.
+///
+/// The SGTL5000 has no data output in SPI mode, so registers cannot
+/// be read back. Reads are served from a shadow copy that starts out
+/// with the reset values and follows every write. Registers outside
+/// the register map read as 0.
+pub struct SpiInterface<SPI> {
+    spi: SPI,
+    shadow: [u16; REGISTER_MAP.len()],
+}
+
+impl<SPI: SpiDevice> SpiInterface<SPI> {
+    /// Expects the codec to be freshly reset
+    pub fn new(spi: SPI) -> Self {
+        SpiInterface {
+            spi,
+            shadow: reset_values(),
+        }
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI: SpiDevice> RegisterInterface for SpiInterface<SPI> {
+    type Error = SPI::Error;
+
+    fn read_register(&mut self, addr: u16) -> Result<u16, Self::Error> {
+        Ok(register_index(addr).map_or(0, |index| self.shadow[index]))
+    }
+
+    fn can_read(&self) -> bool {
+        false
+    }
+
+    fn write_register(&mut self, addr: u16, value: u16) -> Result<(), Self::Error> {
+        // Register addr and value in one chip select cycle
+        let mut buf = [0u8; 4];
+        BigEndian::write_u16(&mut buf[0..2], addr);
+        BigEndian::write_u16(&mut buf[2..4], value);
+        self.spi.write(&buf)?;
+
+        if let Some(index) = register_index(addr) {
+            self.shadow[index] = written_value(index, self.shadow[index], value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sim::CountingDelay;
+    use embedded_hal::spi::{ErrorKind, ErrorType, Operation};
+    use crate::clock::Mclk;
+    use crate::config::Config;
+    use crate::control::SGTL5000Control;
+    use crate::recorder::{Access, Entry};
+
+    /// Records every chip select cycle
+    struct SpiRecorder {
+        frames: [[u8; 4]; 32],
+        len: usize,
+    }
+
+    impl ErrorType for SpiRecorder {
+        type Error = ErrorKind;
+    }
+
+    impl SpiDevice for SpiRecorder {
+        fn transaction(&mut self, operations: &mut [Operation<u8>]) -> Result<(), ErrorKind> {
+            let mut frame = [0; 4];
+            let mut pos = 0;
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => {
+                        frame[pos..pos + bytes.len()].copy_from_slice(bytes);
+                        pos += bytes.len();
+                    }
+                    _ => return Err(ErrorKind::Other),
+                }
+            }
+            assert_eq!(4, pos);
+            self.frames[self.len] = frame;
+            self.len += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_spi_framing() {
+        let spi = SpiRecorder { frames: [[0; 4]; 32], len: 0 };
+        let mut interface = SpiInterface::new(spi);
+        interface.write_register(0x0030, 0x4060).unwrap();
+        interface.write_register(0x0100, 0x0011).unwrap();
+        let spi = interface.release();
+        assert_eq!(2, spi.len);
+        assert_eq!([0x00, 0x30, 0x40, 0x60], spi.frames[0]);
+        assert_eq!([0x01, 0x00, 0x00, 0x11], spi.frames[1]);
+    }
+
+    #[test]
+    fn test_spi_shadow() {
+        let spi = SpiRecorder { frames: [[0; 4]; 32], len: 0 };
+        let mut interface = SpiInterface::new(spi);
+        assert_eq!(Ok(0xA011), interface.read_register(0x0000));
+        assert_eq!(Ok(0x7060), interface.read_register(0x0030));
+        interface.write_register(0x0002, 0xFFFF).unwrap();
+        assert_eq!(Ok(0x0073), interface.read_register(0x0002));
+        // Reading does not touch the bus
+        assert_eq!(1, interface.release().len);
+    }
+
+    #[test]
+    fn test_spi_control() {
+        let spi = SpiRecorder { frames: [[0; 4]; 32], len: 0 };
+        let control = SGTL5000Control::new_spi(spi).unwrap();
+        let spi = control.release();
+
+        // Same writes as over I2C
+        let golden = include_str!("../tests/golden/init.log");
+        let writes = golden.lines()
+            .filter_map(Entry::parse)
+            .filter(|entry| entry.access == Access::Write);
+        let mut len = 0;
+        for (frame, entry) in spi.frames.iter().zip(writes) {
+            let mut expected = [0; 4];
+            BigEndian::write_u16(&mut expected[0..2], entry.addr);
+            BigEndian::write_u16(&mut expected[2..4], entry.value);
+            assert_eq!(expected, *frame);
+            len += 1;
+        }
+        assert_eq!(len, spi.len);
+    }
+
+    #[test]
+    fn test_spi_pll() {
+        let spi = SpiRecorder { frames: [[0; 4]; 32], len: 0 };
+        let mut control = SGTL5000Control::new_spi(spi).unwrap();
+        // PLL_IS_LOCKED cannot be read, so wait out the lock time
+        let mut delay = CountingDelay(0);
+        control.set_sample_rate(12_000_000, 48_000, &mut delay).unwrap();
+        assert_eq!(10_000, delay.0);
+        control.wait_pll_lock(&mut delay).unwrap();
+        assert_eq!(20_000, delay.0);
+        let spi = SpiRecorder { frames: [[0; 4]; 32], len: 0 };
+        let config = Config::builder()
+            .mclk(Mclk::Pll(12_000_000))
+            .build();
+        SGTL5000Control::new_spi_with_config(spi, &config, &mut delay).unwrap();
+        assert_eq!(30_000, delay.0);
+    }
+}
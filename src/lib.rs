@@ -0,0 +1,63 @@
+//! Driver for the SGTL5000 low power stereo audio codec
+//!
+//! https://www.nxp.com/docs/en/data-sheet/SGTL5000.pdf
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[macro_use]
+extern crate bitfield;
+extern crate byteorder;
+extern crate embedded_hal;
+extern crate embedded_hal_async;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+use crate::interface::I2cInterface;
+
+mod volume;
+pub use crate::volume::Volume;
+pub mod registers;
+pub use crate::registers::{DapSource, I2sMode, MclkRatio, RateMode, SampleRate, Source, WordLength};
+pub mod clock;
+pub use crate::clock::{Clock, Mclk, PllLock};
+pub mod i2s;
+pub use crate::i2s::{DataFormat, I2sFormat, I16In32, I20, I24, Sample, SclkRatio};
+pub mod config;
+pub use crate::config::{Blocks, Config, ConfigBuilder, Routing, Supply, Volumes};
+mod sequence;
+pub mod interface;
+pub use crate::interface::{Address, RegisterInterface};
+pub mod cache;
+mod control;
+pub use crate::control::SGTL5000Control;
+mod control_async;
+pub use crate::control_async::SGTL5000ControlAsync;
+mod error;
+pub use crate::error::Error;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub mod recorder;
+
+
+/// https://www.nxp.com/docs/en/data-sheet/SGTL5000.pdf
+pub struct SGTL5000<IF: RegisterInterface> {
+    pub control: SGTL5000Control<IF>,
+}
+
+impl<I2C: I2c> SGTL5000<I2cInterface<I2C>> {
+    pub fn new(i2c: I2C, address: Address) -> Result<Self, Error<I2C::Error>> {
+        let control = SGTL5000Control::new(i2c, address)?;
+        let sgtl5000 = Self {
+            control,
+        };
+        Ok(sgtl5000)
+    }
+
+    pub fn with_config<D: DelayNs>(i2c: I2C, address: Address, config: &Config, delay: &mut D) -> Result<Self, Error<I2C::Error>> {
+        let control = SGTL5000Control::with_config(i2c, address, config, delay)?;
+        Ok(Self { control })
+    }
+}
@@ -0,0 +1,411 @@
+//! Capture and replay of register accesses
+//!
+//! [`Recorder`] wraps an I2C bus and logs every register read and
+//! write going through it. The [`Log`] prints one access per line:
+//!
+//! ```text
+//! R 0000 A011
+//! W 0030 7060
+//! ```
+//!
+//! [`Replayer`] takes such a text log and plays the codec side of it
+//! back to the driver, failing as soon as the driver deviates from
+//! the recorded sequence.
+
+use core::fmt;
+use core::str::Lines;
+use embedded_hal::i2c::{self, ErrorKind, Operation};
+
+/// Maximum number of accesses a [`Log`] holds
+pub const LOG_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// One 16-bit register access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub access: Access,
+    pub addr: u16,
+    pub value: u16,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => 'R',
+            Access::Write => 'W',
+        };
+        write!(f, "{} {:04X} {:04X}", access, self.addr, self.value)
+    }
+}
+
+impl Entry {
+    /// Parse a line as printed by `Display`
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let access = match words.next()? {
+            "R" => Access::Read,
+            "W" => Access::Write,
+            _ => return None,
+        };
+        let addr = u16::from_str_radix(words.next()?, 16).ok()?;
+        let value = u16::from_str_radix(words.next()?, 16).ok()?;
+        if words.next().is_some() {
+            return None;
+        }
+        Some(Entry { access, addr, value })
+    }
+}
+
+/// Ordered register accesses
+pub struct Log {
+    entries: [Entry; LOG_CAPACITY],
+    len: usize,
+    /// Accesses that did not fit anymore
+    dropped: usize,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Log {
+    pub fn new() -> Self {
+        let empty = Entry {
+            access: Access::Read,
+            addr: 0,
+            value: 0,
+        };
+        Log {
+            entries: [empty; LOG_CAPACITY],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if self.len < LOG_CAPACITY {
+            self.entries[self.len] = entry;
+            self.len += 1;
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries[..self.len]
+    }
+
+    /// Number of accesses that were not recorded because the log was
+    /// full
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.dropped = 0;
+    }
+}
+
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries() {
+            writeln!(f, "{}", entry)?;
+        }
+        if self.dropped > 0 {
+            writeln!(f, "# {} more accesses dropped", self.dropped)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs all register accesses that go through the wrapped bus
+pub struct Recorder<I2C> {
+    i2c: I2C,
+    /// Register address for the next read
+    addr: u16,
+    log: Log,
+}
+
+impl<I2C> Recorder<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Recorder {
+            i2c,
+            addr: 0,
+            log: Log::new(),
+        }
+    }
+
+    pub fn log(&self) -> &Log {
+        &self.log
+    }
+
+    pub fn log_mut(&mut self) -> &mut Log {
+        &mut self.log
+    }
+
+    /// Give back the bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn log_write(&mut self, bytes: &[u8]) {
+        if bytes.len() < 2 {
+            return;
+        }
+
+        self.addr = u16::from(bytes[0]) << 8 | u16::from(bytes[1]);
+        for value in bytes[2..].chunks(2) {
+            let value = u16::from(value[0]) << 8 | value.get(1).map_or(0, |&b| u16::from(b));
+            self.log.push(Entry {
+                access: Access::Write,
+                addr: self.addr,
+                value,
+            });
+            self.addr = self.addr.wrapping_add(2);
+        }
+    }
+
+    fn log_read(&mut self, buffer: &[u8]) {
+        for value in buffer.chunks(2) {
+            let value = u16::from(value[0]) << 8 | value.get(1).map_or(0, |&b| u16::from(b));
+            self.log.push(Entry {
+                access: Access::Read,
+                addr: self.addr,
+                value,
+            });
+            self.addr = self.addr.wrapping_add(2);
+        }
+    }
+}
+
+impl<I2C: i2c::ErrorType> i2c::ErrorType for Recorder<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: i2c::I2c> i2c::I2c for Recorder<I2C> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), Self::Error> {
+        self.i2c.transaction(address, operations)?;
+
+        for operation in operations.iter() {
+            match operation {
+                Operation::Write(bytes) => self.log_write(bytes),
+                Operation::Read(buffer) => self.log_read(buffer),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    /// Line could not be parsed
+    Parse { line: usize },
+    /// The driver did something else than recorded in this line
+    Mismatch { line: usize, actual: Entry },
+    /// The driver made more accesses than recorded
+    UnexpectedEnd { actual: Entry },
+    /// The driver made fewer accesses than recorded
+    Remaining { line: usize },
+    /// Transfers are made of a 16-bit register address and 16-bit
+    /// values
+    InvalidLength(usize),
+}
+
+impl i2c::Error for ReplayError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// Plays back a text [`Log`] as the codec
+pub struct Replayer<'a> {
+    lines: Lines<'a>,
+    line: usize,
+    /// Register address for the next read
+    addr: u16,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(log: &'a str) -> Self {
+        Replayer {
+            lines: log.lines(),
+            line: 0,
+            addr: 0,
+        }
+    }
+
+    /// Next recorded entry, skipping empty lines and `#` comments
+    fn next_entry(&mut self) -> Option<Result<Entry, ReplayError>> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some(Entry::parse(line).ok_or(ReplayError::Parse { line: self.line }));
+        }
+    }
+
+    /// Check the driver performed every recorded access
+    pub fn finish(mut self) -> Result<(), ReplayError> {
+        match self.next_entry() {
+            None => Ok(()),
+            Some(Err(e)) => Err(e),
+            Some(Ok(_)) => Err(ReplayError::Remaining { line: self.line }),
+        }
+    }
+
+    /// Match an access by the driver against the next recorded entry,
+    /// returning the recorded one
+    fn replay(&mut self, actual: Entry) -> Result<Entry, ReplayError> {
+        let expected = match self.next_entry() {
+            None => return Err(ReplayError::UnexpectedEnd { actual }),
+            Some(entry) => entry?,
+        };
+        let matches = expected.access == actual.access
+            && expected.addr == actual.addr
+            && (actual.access == Access::Read || expected.value == actual.value);
+        if !matches {
+            return Err(ReplayError::Mismatch { line: self.line, actual });
+        }
+        Ok(expected)
+    }
+}
+
+impl<'a> Replayer<'a> {
+    fn replay_write(&mut self, bytes: &[u8]) -> Result<(), ReplayError> {
+        if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+            return Err(ReplayError::InvalidLength(bytes.len()));
+        }
+
+        self.addr = u16::from(bytes[0]) << 8 | u16::from(bytes[1]);
+        for value in bytes[2..].chunks(2) {
+            let value = u16::from(value[0]) << 8 | u16::from(value[1]);
+            self.replay(Entry {
+                access: Access::Write,
+                addr: self.addr,
+                value,
+            })?;
+            self.addr = self.addr.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    fn replay_read(&mut self, buffer: &mut [u8]) -> Result<(), ReplayError> {
+        if !buffer.len().is_multiple_of(2) {
+            return Err(ReplayError::InvalidLength(buffer.len()));
+        }
+
+        for value in buffer.chunks_mut(2) {
+            let entry = self.replay(Entry {
+                access: Access::Read,
+                addr: self.addr,
+                value: 0,
+            })?;
+            value[0] = (entry.value >> 8) as u8;
+            value[1] = entry.value as u8;
+            self.addr = self.addr.wrapping_add(2);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> i2c::ErrorType for Replayer<'a> {
+    type Error = ReplayError;
+}
+
+impl<'a> i2c::I2c for Replayer<'a> {
+    fn transaction(&mut self, _address: u8, operations: &mut [Operation]) -> Result<(), ReplayError> {
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => self.replay_write(bytes)?,
+                Operation::Read(buffer) => self.replay_read(buffer)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use self::std::string::ToString;
+    use super::*;
+    use crate::control::SGTL5000Control;
+    use crate::interface::Address;
+    use crate::sim::Simulator;
+
+    /// Register accesses of `SGTL5000Control::new()`
+    ///
+    /// Run the tests with `UPDATE_GOLDEN=1` to regenerate after an
+    /// intended change to the power-up sequence.
+    const INIT_GOLDEN: &str = include_str!("../tests/golden/init.log");
+    const INIT_GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/init.log");
+
+    #[test]
+    fn test_init_golden() {
+        let control = SGTL5000Control::new(Recorder::new(Simulator::new()), Address::Adr0Low).unwrap();
+        let recorder = control.release();
+        assert_eq!(0, recorder.log().dropped());
+        let actual = recorder.log().to_string();
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(INIT_GOLDEN_PATH, &actual).unwrap();
+            return;
+        }
+        let mut expected_lines = INIT_GOLDEN.lines();
+        let mut actual_lines = actual.lines();
+        for line in 1.. {
+            match (expected_lines.next(), actual_lines.next()) {
+                (None, None) => break,
+                (expected, actual) =>
+                    assert_eq!(expected, actual, "{} line {}", INIT_GOLDEN_PATH, line),
+            }
+        }
+    }
+
+    #[test]
+    fn test_init_replay() {
+        let control = SGTL5000Control::new(Replayer::new(INIT_GOLDEN), Address::Adr0Low).unwrap();
+        control.release().finish().unwrap();
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let log = "# CHIP_ID\nR 0000 A011\n\nW 0030 1234\n";
+        match SGTL5000Control::new(Replayer::new(log), Address::Adr0Low) {
+            Err(crate::Error::I2cError(ReplayError::Mismatch { line: 4, actual })) =>
+                assert_eq!(Access::Read, actual.access),
+            _ => panic!("replay should fail"),
+        }
+    }
+
+    #[test]
+    fn test_replay_identification() {
+        match SGTL5000Control::new(Replayer::new("R 0000 1234"), Address::Adr0Low) {
+            Err(crate::Error::Identification) => {}
+            _ => panic!("replay should fail"),
+        }
+    }
+
+    #[test]
+    fn test_entry_parse() {
+        let entry = Entry::parse("W 002E 1F1F").unwrap();
+        assert_eq!(Access::Write, entry.access);
+        assert_eq!(0x002E, entry.addr);
+        assert_eq!(0x1F1F, entry.value);
+        assert_eq!("W 002E 1F1F", entry.to_string());
+        assert_eq!(None, Entry::parse("X 002E 1F1F"));
+        assert_eq!(None, Entry::parse("W 002E"));
+    }
+}
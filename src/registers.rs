@@ -1,226 +1,650 @@
-pub(crate) trait I2cRegister {
+use core::convert::TryFrom;
+use core::fmt;
+
+use bitfield::BitRange;
+
+/// A typed SGTL5000 register
+pub trait I2cRegister: Sized {
+    /// Datasheet name
+    const NAME: &'static str;
+    /// Register address
+    const ADDR: u16;
+    /// Value after power-on reset
+    const RESET: u16;
+    /// Bits that can be written
+    const WRITABLE_MASK: u16;
+    /// Bits that only the codec changes, writes leave them alone
+    const READ_ONLY_MASK: u16;
+
     fn new(value: u16) -> Self;
     fn to_inner(&self) -> u16;
-    fn register_addr() -> u16;
+    /// Print every readable field on a line of its own
+    fn write_fields<W: fmt::Write>(&self, w: &mut W) -> fmt::Result;
+
+    fn register_addr() -> u16 {
+        Self::ADDR
+    }
+
+    /// The register at its reset value
+    fn reset() -> Self {
+        Self::new(Self::RESET)
+    }
+
+    /// Bits without a function that should be written as 0
+    fn reserved_mask() -> u16 {
+        !(Self::WRITABLE_MASK | Self::READ_ONLY_MASK)
+    }
 }
 
-bitfield!{
-    pub struct ChipId(u16);
-    impl Debug;
-    /// SGTL5000 Part ID
-    pub u8, partid, _: 15, 8;
-    /// SGTL5000 Revision ID
-    pub u8, revid, _: 7, 0;
+/// Declare a register with its datasheet name, address, reset value
+/// and access masks followed by its bitfields:
+///
+/// ```ignore
+/// register!{
+///     ChipId(CHIP_ID): 0x0000, reset 0xA011, writable 0x0000, read_only 0xFFFF;
+///     pub u8, partid, _: 15, 8;
+/// }
+/// ```
+///
+/// `read_only` may be left out if there are no such bits. Fields
+/// declared as `pub enum Type, getter, setter: hi, lo;` are accessed
+/// through a [`field_enum!`] type instead of a raw number.
+macro_rules! register {
+    (
+        $(#[$attr:meta])*
+        $name:ident($reg:ident): $addr:literal, reset $reset:literal, writable $writable:literal;
+        $($fields:tt)*
+    ) => {
+        register!{
+            $(#[$attr])*
+            $name($reg): $addr, reset $reset, writable $writable, read_only 0x0000;
+            $($fields)*
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $name:ident($reg:ident): $addr:literal, reset $reset:literal, writable $writable:literal, read_only $read_only:literal;
+        $($fields:tt)*
+    ) => {
+        register!{@fields $name [$(#[$attr])* $reg $addr $reset $writable $read_only] [] [] $($fields)*}
+    };
+    // Sort the fields into those for `bitfield!` and the getters to
+    // dump
+    (@fields $name:ident $head:tt [$($bitfields:tt)*] [$($getters:ident)*]
+        $(#[$field_attr:meta])*
+        pub enum $ty:ident, $getter:ident, $setter:ident: $hi:literal, $lo:literal;
+        $($rest:tt)*
+    ) => {
+        impl $name {
+            $(#[$field_attr])*
+            pub fn $getter(&self) -> Result<$ty, Reserved> {
+                $ty::try_from(BitRange::<u8>::bit_range(self, $hi, $lo))
+            }
+
+            pub fn $setter(&mut self, value: $ty) {
+                BitRange::<u8>::set_bit_range(self, $hi, $lo, value.into())
+            }
+        }
+
+        register!{@fields $name $head [$($bitfields)*] [$($getters)* $getter] $($rest)*}
+    };
+    (@fields $name:ident $head:tt [$($bitfields:tt)*] [$($getters:ident)*]
+        $(#[$field_attr:meta])*
+        pub $getter:ident, $setter:tt: $bit:literal;
+        $($rest:tt)*
+    ) => {
+        register!{@fields $name $head
+            [$($bitfields)* $(#[$field_attr])* pub $getter, $setter: $bit;]
+            [$($getters)* $getter]
+            $($rest)*}
+    };
+    (@fields $name:ident $head:tt [$($bitfields:tt)*] [$($getters:ident)*]
+        $(#[$field_attr:meta])*
+        pub $ty:ident, $getter:ident, $setter:tt: $hi:literal, $lo:literal;
+        $($rest:tt)*
+    ) => {
+        register!{@fields $name $head
+            [$($bitfields)* $(#[$field_attr])* pub $ty, $getter, $setter: $hi, $lo;]
+            [$($getters)* $getter]
+            $($rest)*}
+    };
+    (@fields $name:ident
+        [$(#[$attr:meta])* $reg:ident $addr:literal $reset:literal $writable:literal $read_only:literal]
+        [$($bitfields:tt)*] [$($getter:ident)*]
+    ) => {
+        bitfield!{
+            #[doc = stringify!($reg)]
+            $(#[$attr])*
+            pub struct $name(u16);
+            impl Debug;
+            $($bitfields)*
+        }
+
+        impl I2cRegister for $name {
+            const NAME: &'static str = stringify!($reg);
+            const ADDR: u16 = $addr;
+            const RESET: u16 = $reset;
+            const WRITABLE_MASK: u16 = $writable;
+            const READ_ONLY_MASK: u16 = $read_only;
+
+            fn new(value: u16) -> Self {
+                $name(value)
+            }
+            fn to_inner(&self) -> u16 {
+                self.0
+            }
+            fn write_fields<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+                $(
+                    write!(w, "    {}: ", stringify!($getter))?;
+                    self.$getter().write_value(w)?;
+                    writeln!(w)?;
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
+/// How a field value shows up in a register dump
+trait FieldValue {
+    fn write_value<W: fmt::Write>(&self, w: &mut W) -> fmt::Result;
 }
 
-impl I2cRegister for ChipId {
-    fn new(value: u16) -> Self {
-        ChipId(value)
+impl FieldValue for bool {
+    fn write_value<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        write!(w, "{}", self)
     }
-    fn to_inner(&self) -> u16 {
-        self.0
+}
+
+impl FieldValue for u8 {
+    fn write_value<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        write!(w, "{:#x}", self)
     }
-    fn register_addr() -> u16 {
-        0x0000
+}
+
+impl FieldValue for u16 {
+    fn write_value<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        write!(w, "{:#x}", self)
     }
 }
 
-bitfield!{
-    pub struct ChipDigPower(u16);
-    impl Debug;
-    pub adc_powerup, set_adc_powerup: 6;
-    pub dac_powerup, set_dac_powerup: 5;
-    pub dap_powerup, set_dap_powerup: 4;
-    pub i2s_out_powerup, set_i2s_out_powerup: 1;
-    pub i2s_in_powerup, set_i2s_in_powerup: 0;
+/// Enum fields by name
+impl<E: fmt::Debug> FieldValue for Result<E, Reserved> {
+    fn write_value<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        match self {
+            Ok(value) => write!(w, "{:?}", value),
+            Err(Reserved(raw)) => write!(w, "reserved {:#x}", raw),
+        }
+    }
+}
+
+/// Lists all registers in address order
+macro_rules! register_map {
+    ($($name:ident,)*) => {
+        /// Register address, reset value and writable bit mask
+        pub(crate) const REGISTER_MAP: [(u16, u16, u16); [$(stringify!($name)),*].len()] = [
+            $((<$name>::ADDR, <$name>::RESET, <$name>::WRITABLE_MASK),)*
+        ];
+
+        /// Datasheet name of the register at `addr`
+        pub fn register_name(addr: u16) -> Option<&'static str> {
+            $(
+                if addr == <$name>::ADDR {
+                    return Some(<$name>::NAME);
+                }
+            )*
+            None
+        }
+
+        /// Print the decoded fields of a value of the register at
+        /// `addr`, nothing for unknown registers
+        pub fn write_fields<W: fmt::Write>(w: &mut W, addr: u16, value: u16) -> fmt::Result {
+            $(
+                if addr == <$name>::ADDR {
+                    return <$name>::new(value).write_fields(w);
+                }
+            )*
+            Ok(())
+        }
+    };
+}
+
+/// Print a register value with its address, name and decoded fields
+pub fn write_register<W: fmt::Write>(w: &mut W, addr: u16, value: u16) -> fmt::Result {
+    writeln!(w, "{:04X} {:04X} {}", addr, value, register_name(addr).unwrap_or("?"))?;
+    write_fields(w, addr, value)
+}
+
+register_map!{
+    ChipId,
+    ChipDigPower,
+    ChipClkCtrl,
+    ChipI2sCtrl,
+    ChipSssCtrl,
+    ChipAdcdacCtrl,
+    ChipDacVol,
+    ChipPadStrength,
+    ChipAnaAdcCtrl,
+    ChipAnaHpCtrl,
+    ChipAnaCtrl,
+    ChipLinregCtrl,
+    ChipRefCtrl,
+    ChipMicCtrl,
+    ChipLineOutCtrl,
+    ChipLineOutVol,
+    ChipAnaPower,
+    ChipPllCtrl,
+    ChipClkTopCtrl,
+    ChipAnaStatus,
+    ChipAnaTest1,
+    ChipAnaTest2,
+    ChipShortCtrl,
+    DapControl,
+    DapPeq,
+    DapBassEnhance,
+    DapBassEnhanceCtrl,
+    DapAudioEq,
+    DapSgtlSurround,
+    DapFilterCoefAccess,
+    DapCoefWrB0Msb,
+    DapCoefWrB0Lsb,
+    DapAudioEqBassBand0,
+    DapAudioEqBand1,
+    DapAudioEqBand2,
+    DapAudioEqBand3,
+    DapAudioEqTrebleBand4,
+    DapMainChan,
+    DapMixChan,
+    DapAvcCtrl,
+    DapAvcThreshold,
+    DapAvcAttack,
+    DapAvcDecay,
+    DapCoefWrB1Msb,
+    DapCoefWrB1Lsb,
+    DapCoefWrB2Msb,
+    DapCoefWrB2Lsb,
+    DapCoefWrA1Msb,
+    DapCoefWrA1Lsb,
+    DapCoefWrA2Msb,
+    DapCoefWrA2Lsb,
 }
 
-impl I2cRegister for ChipDigPower {
-    fn new(value: u16) -> Self {
-        ChipDigPower(value)
+/// Position of a register in [`REGISTER_MAP`]
+pub(crate) fn register_index(addr: u16) -> Option<usize> {
+    REGISTER_MAP.iter()
+        .position(|&(reg_addr, _, _)| reg_addr == addr)
+}
+
+/// Bits of a register that can be written, all for unknown ones
+pub(crate) fn writable_mask(addr: u16) -> u16 {
+    register_index(addr)
+        .map_or(0xFFFF, |index| REGISTER_MAP[index].2)
+}
+
+/// Every register at its reset value, in [`REGISTER_MAP`] order
+pub(crate) fn reset_values() -> [u16; REGISTER_MAP.len()] {
+    let mut values = [0; REGISTER_MAP.len()];
+    for (value, &(_, reset, _)) in values.iter_mut().zip(REGISTER_MAP.iter()) {
+        *value = reset;
     }
-    fn to_inner(&self) -> u16 {
-        self.0
+    values
+}
+
+/// Register content after writing `value` over `old`, read-only bits
+/// keep their value
+pub(crate) fn written_value(index: usize, old: u16, value: u16) -> u16 {
+    let (_, _, mask) = REGISTER_MAP[index];
+    (old & !mask) | (value & mask)
+}
+
+/// Raw field value that the datasheet marks as reserved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reserved(pub u8);
+
+/// Declare an enum for a multi-bit field, convertible from and to
+/// the raw field value
+macro_rules! field_enum {
+    (
+        $(#[$attr:meta])*
+        $name:ident {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant:ident = $value:literal,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $(
+                $(#[$variant_attr])*
+                $variant = $value,
+            )*
+        }
+
+        impl From<$name> for u8 {
+            fn from(value: $name) -> u8 {
+                value as u8
+            }
+        }
+
+        impl TryFrom<u8> for $name {
+            type Error = Reserved;
+
+            fn try_from(value: u8) -> Result<Self, Reserved> {
+                match value {
+                    $($value => Ok($name::$variant),)*
+                    _ => Err(Reserved(value)),
+                }
+            }
+        }
+    };
+}
+
+field_enum!{
+    /// Internal system sample rate SYS_FS
+    SampleRate {
+        /// 32 kHz
+        Hz32000 = 0,
+        /// 44.1 kHz
+        Hz44100 = 1,
+        /// 48 kHz
+        Hz48000 = 2,
+        /// 96 kHz
+        Hz96000 = 3,
     }
-    fn register_addr() -> u16 {
-        0x0002
+}
+
+field_enum!{
+    /// Sample rate relative to SYS_FS
+    RateMode {
+        Div1 = 0,
+        Div2 = 1,
+        Div4 = 2,
+        Div6 = 3,
     }
 }
 
-bitfield!{
-    pub struct ChipClkCtrl(u16);
-    impl Debug;
-    pub u8, rate_mode, set_rate_mode: 5, 4;
-    pub u8, sys_fs, set_sys_fs: 3, 2;
-    pub u8, mclk_freq, set_mclk_freq: 1, 0;
+field_enum!{
+    /// SYS_MCLK frequency relative to SYS_FS
+    MclkRatio {
+        /// 256 * Fs
+        Fs256 = 0,
+        /// 384 * Fs
+        Fs384 = 1,
+        /// 512 * Fs
+        Fs512 = 2,
+        /// Clock from the PLL
+        Pll = 3,
+    }
 }
 
-impl I2cRegister for ChipClkCtrl {
-    fn new(value: u16) -> Self {
-        ChipClkCtrl(value)
+field_enum!{
+    /// I2S data length
+    WordLength {
+        Bits32 = 0,
+        Bits24 = 1,
+        Bits20 = 2,
+        Bits16 = 3,
     }
-    fn to_inner(&self) -> u16 {
-        self.0
+}
+
+field_enum!{
+    /// I2S data format
+    I2sMode {
+        /// I2S with LRALIGN clear, left-justified with LRALIGN set
+        I2sOrLeftJustified = 0,
+        RightJustified = 1,
+        /// PCM format A with LRALIGN clear, B with LRALIGN set
+        Pcm = 2,
     }
-    fn register_addr() -> u16 {
-        0x0004
+}
+
+field_enum!{
+    /// Data source for the digital audio routing in CHIP_SSS_CTRL
+    Source {
+        Adc = 0,
+        I2sIn = 1,
+        /// Only for the DAC and I2S_DOUT, reserved as DAP input
+        Dap = 3,
+    }
+}
+
+field_enum!{
+    /// Data source for the DAP and DAP mixer, which cannot take the
+    /// DAP output
+    DapSource {
+        Adc = 0,
+        I2sIn = 1,
     }
 }
 
-bitfield!{
-    pub struct ChipI2sCtrl(u16);
-    impl Debug;
+/// A value that does not fit a register field or falls into a range
+/// the datasheet marks as reserved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange {
+    /// Register name as in the datasheet
+    pub register: &'static str,
+    pub field: &'static str,
+    pub value: u16,
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {:#x} out of range", self.register, self.field, self.value)
+    }
+}
+
+/// Declare setters that check the value against the valid encodings
+/// of a field before writing it
+macro_rules! checked_setters {
+    (
+        $name:ident {
+            $($checked:ident => $setter:ident($ty:ty): $($valid:pat)|+,)*
+        }
+    ) => {
+        impl $name {
+            $(
+                pub fn $checked(&mut self, value: $ty) -> Result<(), OutOfRange> {
+                    match value {
+                        $($valid)|+ => {
+                            self.$setter(value);
+                            Ok(())
+                        }
+                        _ => Err(OutOfRange {
+                            register: $name::NAME,
+                            field: stringify!($setter),
+                            value: value.into(),
+                        }),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+register!{
+    ChipId(CHIP_ID): 0x0000, reset 0xA011, writable 0x0000, read_only 0xFFFF;
+    /// SGTL5000 Part ID
+    pub u8, partid, _: 15, 8;
+    /// SGTL5000 Revision ID
+    pub u8, revid, _: 7, 0;
+}
+
+register!{
+    ChipDigPower(CHIP_DIG_POWER): 0x0002, reset 0x0000, writable 0x0073;
+    /// Enable/disable the ADC block, both digital and analog
+    pub adc_powerup, set_adc_powerup: 6;
+    /// Enable/disable the DAC block, both analog and digital
+    pub dac_powerup, set_dac_powerup: 5;
+    /// Enable/disable the DAP block
+    pub dap_powerup, set_dap_powerup: 4;
+    /// Enable/disable the I2S data output
+    pub i2s_out_powerup, set_i2s_out_powerup: 1;
+    /// Enable/disable the I2S data input
+    pub i2s_in_powerup, set_i2s_in_powerup: 0;
+}
+
+register!{
+    ChipClkCtrl(CHIP_CLK_CTRL): 0x0004, reset 0x0008, writable 0x003F;
+    /// Sample rate divider relative to SYS_FS
+    pub enum RateMode, rate_mode, set_rate_mode: 5, 4;
+    /// Internal system sample rate
+    pub enum SampleRate, sys_fs, set_sys_fs: 3, 2;
+    /// SYS_MCLK frequency as a multiple of SYS_FS, or the PLL
+    pub enum MclkRatio, mclk_freq, set_mclk_freq: 1, 0;
+}
+
+register!{
+    ChipI2sCtrl(CHIP_I2S_CTRL): 0x0006, reset 0x0010, writable 0x01FF;
+    /// I2S_SCLK frequency: 32*Fs if set, 64*Fs otherwise
     pub sclkfreq, set_sclkfreq: 8;
+    /// Master mode: the codec generates I2S_SCLK and I2S_LRCLK
     pub ms, set_ms: 7;
-    pub sclk_pcmsync, set_pcmsync: 7;
+    /// Sample data on the falling instead of rising I2S_SCLK edge
     pub sclk_inv, set_sclk_inv: 6;
-    pub u8, dlen, set_dlen: 5, 4;
-    pub u8, i2s_mode, set_i2s_mode: 3, 2;
+    /// I2S data length
+    pub enum WordLength, dlen, set_dlen: 5, 4;
+    /// I2S, left-justified, right-justified or PCM format
+    pub enum I2sMode, i2s_mode, set_i2s_mode: 3, 2;
+    /// Data word starts right after the frame sync instead of one
+    /// I2S_SCLK later
     pub lralign, set_lralign: 1;
+    /// Invert I2S_LRCLK polarity
     pub lrpol, set_lrpol: 0;
 }
 
-impl I2cRegister for ChipI2sCtrl {
-    fn new(value: u16) -> Self {
-        ChipI2sCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0006
-    }
-}
-
-bitfield!{
-    pub struct ChipSssCtrl(u16);
-    impl Debug;
+register!{
+    ChipSssCtrl(CHIP_SSS_CTRL): 0x000A, reset 0x0010, writable 0x77F3;
     /// DAP Mixer Input Swap
     pub dap_mix_lrswap, set_dap_mix_lrswap: 14;
     /// DAP Input Swap
     pub dap_lrswap, set_dap_lrswap: 13;
     /// DAC Input Swap
     pub dac_lrswap, set_dac_lrswap: 12;
-    /// 
+    /// I2S_DOUT Swap
     pub i2s_lrswap, set_i2s_lrswap: 10;
-    /// 
-    pub u8, dap_mix_select, set_dap_mix_select: 9, 8;
-    /// 
-    pub u8, dap_select, set_dap_select: 7, 6;
-    /// 
-    pub u8, dac_select, set_dac_select: 5, 4;
-    /// 
-    pub u8, i2s_select, set_i2s_select: 1, 0;
+    /// Data source for the DAP mixer, ADC or I2S_IN
+    pub enum DapSource, dap_mix_select, set_dap_mix_select: 9, 8;
+    /// Data source for the DAP, ADC or I2S_IN
+    pub enum DapSource, dap_select, set_dap_select: 7, 6;
+    /// Data source for the DAC
+    pub enum Source, dac_select, set_dac_select: 5, 4;
+    /// Data source for I2S_DOUT
+    pub enum Source, i2s_select, set_i2s_select: 1, 0;
 }
 
-impl I2cRegister for ChipSssCtrl {
-    fn new(value: u16) -> Self {
-        ChipSssCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x000A
-    }
-}
-
-bitfield!{
-    pub struct ChipAdcdacCtrl(u16);
-    impl Debug;
-    // TODO: rest
-    ///
-    pub dac_mute_right, set_dac_mute_right: 3;
-    ///
-    pub dac_mute_left, set_dac_mute_left: 2;
+register!{
+    ChipAdcdacCtrl(CHIP_ADCDAC_CTRL): 0x000E, reset 0x020C, writable 0x030F, read_only 0x3000;
+    /// Volume ramp of the right DAC channel is in progress
+    pub vol_busy_dac_right, _: 13;
+    /// Volume ramp of the left DAC channel is in progress
+    pub vol_busy_dac_left, _: 12;
+    /// Ramp volume changes instead of applying them immediately
     pub vol_ramp_en, set_vol_ramp_en: 9;
+    /// Exponential instead of linear volume ramp
     pub vol_expo_ramp, set_vol_expo_ramp: 8;
+    /// DAC Right Mute
+    pub dac_mute_right, set_dac_mute_right: 3;
+    /// DAC Left Mute
+    pub dac_mute_left, set_dac_mute_left: 2;
+    /// Freeze the ADC high-pass filter offset register
+    pub adc_hpf_freeze, set_adc_hpf_freeze: 1;
+    /// Bypass the ADC high-pass filter
+    pub adc_hpf_bypass, set_adc_hpf_bypass: 0;
 }
 
-impl I2cRegister for ChipAdcdacCtrl {
-    fn new(value: u16) -> Self {
-        ChipAdcdacCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x000E
-    }
-}
-
-bitfield!{
-    pub struct ChipDacVol(u16);
-    impl Debug;
+register!{
+    ChipDacVol(CHIP_DAC_VOL): 0x0010, reset 0x3C3C, writable 0xFFFF;
     /// DAC Right Channel Volume
     pub u8, dac_vol_right, set_dac_vol_right: 15, 8;
     /// DAC Left Channel Volume
     pub u8, dac_vol_left, set_dac_vol_left: 7, 0;
 }
 
-impl I2cRegister for ChipDacVol {
-    fn new(value: u16) -> Self {
-        ChipDacVol(value)
+checked_setters!{
+    ChipDacVol {
+        try_set_dac_vol_right => set_dac_vol_right(u8): 0x3C..=0xFF,
+        try_set_dac_vol_left => set_dac_vol_left(u8): 0x3C..=0xFF,
     }
-    fn to_inner(&self) -> u16 {
-        self.0
+}
+
+register!{
+    ChipPadStrength(CHIP_PAD_STRENGTH): 0x0014, reset 0x015F, writable 0x03FF;
+    /// I2S_LRCLK pad drive strength
+    pub u8, i2s_lrclk, set_i2s_lrclk: 9, 8;
+    /// I2S_SCLK pad drive strength
+    pub u8, i2s_sclk, set_i2s_sclk: 7, 6;
+    /// I2S_DOUT pad drive strength
+    pub u8, i2s_dout, set_i2s_dout: 5, 4;
+    /// I2C CTRL_DATA pad drive strength
+    pub u8, ctrl_data, set_ctrl_data: 3, 2;
+    /// I2C CTRL_CLK pad drive strength
+    pub u8, ctrl_clk, set_ctrl_clk: 1, 0;
+}
+
+checked_setters!{
+    ChipPadStrength {
+        try_set_i2s_lrclk => set_i2s_lrclk(u8): 0..=3,
+        try_set_i2s_sclk => set_i2s_sclk(u8): 0..=3,
+        try_set_i2s_dout => set_i2s_dout(u8): 0..=3,
+        try_set_ctrl_data => set_ctrl_data(u8): 0..=3,
+        try_set_ctrl_clk => set_ctrl_clk(u8): 0..=3,
     }
-    fn register_addr() -> u16 {
-        0x0010
+}
+
+register!{
+    ChipAnaAdcCtrl(CHIP_ANA_ADC_CTRL): 0x0020, reset 0x0000, writable 0x01FF;
+    /// Shift the ADC analog volume range down by 6 dB
+    pub adc_vol_m6db, set_adc_vol_m6db: 8;
+    /// ADC Right Channel Volume in 1.5 dB steps
+    pub u8, adc_vol_right, set_adc_vol_right: 7, 4;
+    /// ADC Left Channel Volume in 1.5 dB steps
+    pub u8, adc_vol_left, set_adc_vol_left: 3, 0;
+}
+
+checked_setters!{
+    ChipAnaAdcCtrl {
+        try_set_adc_vol_right => set_adc_vol_right(u8): 0x0..=0xF,
+        try_set_adc_vol_left => set_adc_vol_left(u8): 0x0..=0xF,
     }
 }
 
-bitfield!{
-    pub struct ChipAnaHpCtrl(u16);
-    impl Debug;
+register!{
+    ChipAnaHpCtrl(CHIP_ANA_HP_CTRL): 0x0022, reset 0x1818, writable 0x7F7F;
     /// Headphone Right Channel Volume
     pub u8, hp_vol_right, set_hp_vol_right: 14, 8;
     /// Headphone Left Channel Volume
     pub u8, hp_vol_left, set_hp_vol_left: 6, 0;
 }
 
-impl I2cRegister for ChipAnaHpCtrl {
-    fn new(value: u16) -> Self {
-        ChipAnaHpCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0022
+checked_setters!{
+    ChipAnaHpCtrl {
+        try_set_hp_vol_right => set_hp_vol_right(u8): 0x00..=0x7F,
+        try_set_hp_vol_left => set_hp_vol_left(u8): 0x00..=0x7F,
     }
 }
 
-bitfield!{
-    pub struct ChipAnaCtrl(u16);
-    impl Debug;
+register!{
+    ChipAnaCtrl(CHIP_ANA_CTRL): 0x0024, reset 0x0111, writable 0x0177;
     /// LINEOUT mute
     pub mute_lo, set_mute_lo: 8;
     /// Select the headphone input
     pub select_hp, set_select_hp: 6;
-    // TODO: add the rest
+    /// Enable the headphone zero cross detector (ZCD)
+    pub en_zcd_hp, set_en_zcd_hp: 5;
+    /// Mute the headphone outputs
     pub mute_hp, set_mute_hp: 4;
+    /// Select the ADC input: LINEIN if set, microphone otherwise
+    pub select_adc, set_select_adc: 2;
+    /// Enable the ADC analog zero cross detector (ZCD)
+    pub en_zcd_adc, set_en_zcd_adc: 1;
     /// Mute the ADC analog volume
     pub mute_adc, set_mute_adc: 0;
-    /// Enable the headphone zero cross detector (ZCD)
-    pub en_zcd_hp, set_en_zcd_hp: 5;
-}
-
-impl I2cRegister for ChipAnaCtrl {
-    fn new(value: u16) -> Self {
-        ChipAnaCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0024
-    }
 }
 
-bitfield!{
-    pub struct ChipRefCtrl(u16);
-    impl Debug;
+register!{
+    ChipRefCtrl(CHIP_REF_CTRL): 0x0028, reset 0x0000, writable 0x01FF;
     /// Analog Ground Voltage Control
     pub u8, vag_val, set_vag_val: 8, 4;
     /// Bias control
@@ -229,21 +653,33 @@ bitfield!{
     pub small_pop, set_small_pop: 0;
 }
 
-impl I2cRegister for ChipRefCtrl {
-    fn new(value: u16) -> Self {
-        ChipRefCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
+checked_setters!{
+    ChipRefCtrl {
+        try_set_vag_val => set_vag_val(u8): 0x00..=0x1F,
+        try_set_bias_ctrl => set_bias_ctrl(u8): 0..=7,
     }
-    fn register_addr() -> u16 {
-        0x0028
+}
+
+register!{
+    ChipMicCtrl(CHIP_MIC_CTRL): 0x002A, reset 0x0000, writable 0x0373;
+    /// MIC_BIAS output impedance
+    pub u8, bias_resistor, set_bias_resistor: 9, 8;
+    /// MIC_BIAS voltage in 250 mV steps from 1.25 V
+    pub u8, bias_volt, set_bias_volt: 6, 4;
+    /// Microphone amplifier gain
+    pub u8, gain, set_gain: 1, 0;
+}
+
+checked_setters!{
+    ChipMicCtrl {
+        try_set_bias_resistor => set_bias_resistor(u8): 0..=3,
+        try_set_bias_volt => set_bias_volt(u8): 0..=7,
+        try_set_gain => set_gain(u8): 0..=3,
     }
 }
 
-bitfield!{
-    pub struct ChipLinregCtrl(u16);
-    impl Debug;
+register!{
+    ChipLinregCtrl(CHIP_LINREG_CTRL): 0x0026, reset 0x0000, writable 0x006F;
     /// Determines chargepump source when VDDC_ASSN_OVRD is set.
     pub vdcc_man_assn, set_vdcc_man_assn: 6;
     /// Charge pump Source Assignment Override
@@ -252,117 +688,96 @@ bitfield!{
     /// steps. Must clear the LINREG_SIMPLE_POWERUP and
     /// STARTUP_POWERUP bits in the 0x0030 register after power-up,
     /// for this setting to produce the proper VDDD voltage.
-    pub d_programming, set_d_programming: 3, 0;
+    pub u8, d_programming, set_d_programming: 3, 0;
 }
 
-impl I2cRegister for ChipLinregCtrl {
-    fn new(value: u16) -> Self {
-        ChipLinregCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0026
+checked_setters!{
+    ChipLinregCtrl {
+        try_set_d_programming => set_d_programming(u8): 0x0..=0xF,
     }
 }
 
-bitfield!{
-    pub struct ChipLineOutCtrl(u16);
-    impl Debug;
+register!{
+    ChipLineOutCtrl(CHIP_LINE_OUT_CTRL): 0x002C, reset 0x0000, writable 0x0F3F;
     /// Controls the output bias current for the LINEOUT amplifiers.
     pub u8, out_current, set_out_current: 11, 8;
     /// LINEOUT Amplifier Analog Ground Voltage
     pub u8, lo_vagcntrl, set_lo_vagcntrl: 5, 0;
 }
 
-impl I2cRegister for ChipLineOutCtrl {
-    fn new(value: u16) -> Self {
-        ChipLineOutCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x002C
+checked_setters!{
+    ChipLineOutCtrl {
+        try_set_out_current => set_out_current(u8): 0x0 | 0x1 | 0x3 | 0x7 | 0xF,
+        try_set_lo_vagcntrl => set_lo_vagcntrl(u8): 0x00..=0x23,
     }
 }
 
-bitfield!{
-    pub struct ChipAnaPower(u16);
-    impl Debug;
+register!{
+    ChipAnaPower(CHIP_ANA_POWER): 0x0030, reset 0x7060, writable 0x7FFF;
+    /// DAC stereo if set, mono otherwise
     pub dac_mono, set_dac_mono: 14;
+    /// Power up the simple (low power) digital supply regulator
     pub linreg_simple_powerup, set_linreg_simple_powerup: 13;
+    /// Power up the circuitry needed during the power up ramp and reset
     pub startup_powerup, set_startup_powerup: 12;
+    /// Power up the VDDC charge pump block
     pub vddc_chrgpmp_powerup, set_vddc_chrgpmp_powerup: 11;
+    /// PLL Power Up
     pub pll_powerup, set_pll_powerup: 10;
+    /// Power up the primary VDDD linear regulator
     pub linreg_d_powerup, set_linreg_d_powerup: 9;
+    /// Power up the PLL VCO amplifier
     pub vcoamp_powerup, set_vcoamp_powerup: 8;
+    /// Power up the VAG reference buffer
     pub vag_powerup, set_vag_powerup: 7;
+    /// ADC stereo if set, mono otherwise
     pub adc_mono, set_adc_mono: 6;
+    /// Power up the reference bias currents
     pub reftop_powerup, set_reftop_powerup: 5;
+    /// Power up the headphone amplifiers
     pub headphone_powerup, set_headphone_powerup: 4;
+    /// Power up the DACs
     pub dac_powerup, set_dac_powerup: 3;
+    /// Power up the capless headphone mode
     pub capless_headphone_powerup, set_capless_headphone_powerup: 2;
+    /// Power up the ADCs
     pub adc_powerup, set_adc_powerup: 1;
+    /// Power up the LINEOUT amplifiers
     pub lineout_powerup, set_lineout_powerup: 0;
 }
 
-impl I2cRegister for ChipAnaPower {
-    fn new(value: u16) -> Self {
-        ChipAnaPower(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0030
-    }
-}
-
-bitfield!{
-    pub struct ChipPllCtrl(u16);
-    impl Debug;
+register!{
+    ChipPllCtrl(CHIP_PLL_CTRL): 0x0032, reset 0x5000, writable 0xFFFF;
     /// Integer portion of PLL divisor
     pub u8, int_divisor, set_int_divisor: 15, 11;
     /// Fractional portion of PLL divisor
-    pub u8, frac_divisor, set_frac_divisor: 10, 0;
+    pub u16, frac_divisor, set_frac_divisor: 10, 0;
 }
 
-impl I2cRegister for ChipPllCtrl {
-    fn new(value: u16) -> Self {
-        ChipPllCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0032
+checked_setters!{
+    ChipPllCtrl {
+        try_set_int_divisor => set_int_divisor(u8): 0..=31,
+        try_set_frac_divisor => set_frac_divisor(u16): 0..=0x7FF,
     }
 }
 
-bitfield!{
-    pub struct ChipLineOutVol(u16);
-    impl Debug;
+register!{
+    ChipLineOutVol(CHIP_LINE_OUT_VOL): 0x002E, reset 0x0404, writable 0x1F1F;
+    /// LINEOUT Right Channel Output Level
     pub u8, lo_vol_right, set_lo_vol_right: 12, 8;
+    /// LINEOUT Left Channel Output Level
     pub u8, lo_vol_left, set_lo_vol_left: 4, 0;
 }
 
-impl I2cRegister for ChipLineOutVol {
-    fn new(value: u16) -> Self {
-        ChipLineOutVol(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x002E
+checked_setters!{
+    ChipLineOutVol {
+        try_set_lo_vol_right => set_lo_vol_right(u8): 0x00..=0x1F,
+        try_set_lo_vol_left => set_lo_vol_left(u8): 0x00..=0x1F,
     }
 }
 
-bitfield!{
-    pub struct ChipClkTopCtrl(u16);
-    impl Debug;
+register!{
+    ChipClkTopCtrl(CHIP_CLK_TOP_CTRL): 0x0034, reset 0x0000, writable 0x0808;
     /// Setting this bit enables an internal oscillator to be used for
     /// the zero cross detectors, the short detect recovery, and the
     /// charge pump.
@@ -371,57 +786,952 @@ bitfield!{
     pub input_freq_div2, set_input_freq_div2: 3;
 }
 
-impl I2cRegister for ChipClkTopCtrl {
-    fn new(value: u16) -> Self {
-        ChipClkTopCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x0034
+register!{
+    ChipAnaStatus(CHIP_ANA_STATUS): 0x0036, reset 0x0000, writable 0x0000, read_only 0x0310;
+    /// Short detected on the left/right headphone channels
+    pub lrshort_sts, _: 9;
+    /// Short detected on the capless headphone center channel
+    pub cshort_sts, _: 8;
+    /// The PLL is locked
+    pub pll_is_locked, _: 4;
+}
+
+register!{
+    ChipAnaTest1(CHIP_ANA_TEST1): 0x0038, reset 0x01C0, writable 0x3FFF;
+    /// Headphone amplifier quiescent current
+    pub u8, hp_iall_adj, set_hp_iall_adj: 13, 12;
+    /// Headphone amplifier first stage current
+    pub u8, hp_i1_adj, set_hp_i1_adj: 11, 10;
+    /// Headphone anti-pop control
+    pub u8, hp_antipop, set_hp_antipop: 9, 8;
+    /// Headphone amplifier in class AB mode
+    pub hp_classab, set_hp_classab: 7;
+    /// Hold the headphone center channel to ground
+    pub hp_hold_gnd_center, set_hp_hold_gnd_center: 6;
+    /// Hold the headphone outputs to ground
+    pub hp_hold_gnd, set_hp_hold_gnd: 5;
+    /// Double the VAG buffer current
+    pub vag_doub_current, set_vag_doub_current: 4;
+    /// VAG buffer in class A mode
+    pub vag_classa, set_vag_classa: 3;
+    /// Test mode: route the ADC input to the headphone amplifiers
+    pub tm_adcin_tohp, set_tm_adcin_tohp: 2;
+    /// Test mode: select the microphone for the ADC
+    pub tm_select_mic, set_tm_select_mic: 1;
+    /// Enable test mode
+    pub testmode, set_testmode: 0;
+}
+
+checked_setters!{
+    ChipAnaTest1 {
+        try_set_hp_iall_adj => set_hp_iall_adj(u8): 0..=3,
+        try_set_hp_i1_adj => set_hp_i1_adj(u8): 0..=3,
+        try_set_hp_antipop => set_hp_antipop(u8): 0..=3,
     }
 }
 
-bitfield!{
-    pub struct ChipShortCtrl(u16);
-    impl Debug;
+register!{
+    ChipAnaTest2(CHIP_ANA_TEST2): 0x003A, reset 0x0000, writable 0x7FFF;
+    /// Power the LINEOUT amplifiers from VDDA instead of VDDIO
+    pub lineout_to_vdda, set_lineout_to_vdda: 14;
+    /// Spare
+    pub spare, set_spare: 13;
+    /// Route the left DAC channel to both outputs
+    pub monomode_dac, set_monomode_dac: 12;
+    /// Increase the VCO tuning gain
+    pub vco_tune_again, set_vco_tune_again: 11;
+    /// Low-pass filter the LINEOUT VAG
+    pub lo_pass_mastervag, set_lo_pass_mastervag: 10;
+    /// Invert the DAC sample clock
+    pub invert_dac_sample_clock, set_invert_dac_sample_clock: 9;
+    /// Invert the DAC data timing
+    pub invert_dac_data_timing, set_invert_dac_data_timing: 8;
+    /// Extend the DAC return-to-zero time
+    pub dac_extend_rtz, set_dac_extend_rtz: 7;
+    /// Double the DAC current
+    pub dac_double_i, set_dac_double_i: 6;
+    /// Disable the DAC return-to-zero
+    pub dac_dis_rtz, set_dac_dis_rtz: 5;
+    /// DAC in class A mode
+    pub dac_classa, set_dac_classa: 4;
+    /// Invert the ADC sample clock
+    pub invert_adc_sample_clock, set_invert_adc_sample_clock: 3;
+    /// Invert the ADC data timing
+    pub invert_adc_data_timing, set_invert_adc_data_timing: 2;
+    /// Reduce the ADC current
+    pub adc_lessi, set_adc_lessi: 1;
+    /// Disable the ADC dither
+    pub adc_ditheroff, set_adc_ditheroff: 0;
+}
+
+register!{
+    ChipShortCtrl(CHIP_SHORT_CTRL): 0x003C, reset 0x0000, writable 0x777F;
+    /// Right channel headphone short detector trip current
     pub u8, lvladjr, set_lvladjr: 14, 12;
+    /// Left channel headphone short detector trip current
     pub u8, lvladjl, set_lvladjl: 10, 8;
+    /// Capless headphone center channel short detector trip current
     pub u8, lvladjc, set_lvladjc: 6, 4;
+    /// Behaviour of the left/right short detectors
     pub u8, mode_lr, set_mode_lr: 3, 2;
+    /// Behaviour of the center channel short detector
     pub u8, mode_cm, set_mode_cm: 1, 0;
 }
 
-impl I2cRegister for ChipShortCtrl {
-    fn new(value: u16) -> Self {
-        ChipShortCtrl(value)
-    }
-    fn to_inner(&self) -> u16 {
-        self.0
-    }
-    fn register_addr() -> u16 {
-        0x003C
+checked_setters!{
+    ChipShortCtrl {
+        try_set_lvladjr => set_lvladjr(u8): 0..=7,
+        try_set_lvladjl => set_lvladjl(u8): 0..=7,
+        try_set_lvladjc => set_lvladjc(u8): 0..=7,
+        try_set_mode_lr => set_mode_lr(u8): 0..=3,
+        try_set_mode_cm => set_mode_cm(u8): 0..=3,
     }
 }
 
-bitfield!{
-    pub struct DapControl(u16);
-    impl Debug;
+register!{
+    DapControl(DAP_CONTROL): 0x0100, reset 0x0000, writable 0x0011;
     /// Enable/Disable the DAP mixer path
     pub mix_en, set_mix_en: 4;
     /// Enable/Disable digital audio processing (DAP)
     pub dap_en, set_dap_en: 0;
 }
 
-impl I2cRegister for DapControl {
-    fn new(value: u16) -> Self {
-        DapControl(value)
+register!{
+    DapPeq(DAP_PEQ): 0x0102, reset 0x0000, writable 0x0007;
+    /// Number of enabled parametric EQ filters, 0 to 7
+    pub u8, en, set_en: 2, 0;
+}
+
+checked_setters!{
+    DapPeq {
+        try_set_en => set_en(u8): 0..=7,
     }
-    fn to_inner(&self) -> u16 {
-        self.0
+}
+
+register!{
+    DapBassEnhance(DAP_BASS_ENHANCE): 0x0104, reset 0x0040, writable 0x0171;
+    /// Bypass the high-pass filter in the bass enhance path
+    pub bypass_hpf, set_bypass_hpf: 8;
+    /// Bass enhance cutoff frequency
+    pub u8, cutoff, set_cutoff: 6, 4;
+    /// Enable bass enhance
+    pub en, set_en: 0;
+}
+
+checked_setters!{
+    DapBassEnhance {
+        try_set_cutoff => set_cutoff(u8): 0..=7,
     }
-    fn register_addr() -> u16 {
-        0x0100
+}
+
+register!{
+    DapBassEnhanceCtrl(DAP_BASS_ENHANCE_CTRL): 0x0106, reset 0x051F, writable 0x3F7F;
+    /// Mix level of the left/right channels for bass enhance
+    pub u8, lr_level, set_lr_level: 13, 8;
+    /// Level of the harmonics added by bass enhance
+    pub u8, bass_level, set_bass_level: 6, 0;
+}
+
+checked_setters!{
+    DapBassEnhanceCtrl {
+        try_set_lr_level => set_lr_level(u8): 0x00..=0x3F,
+        try_set_bass_level => set_bass_level(u8): 0x00..=0x7F,
+    }
+}
+
+register!{
+    DapAudioEq(DAP_AUDIO_EQ): 0x0108, reset 0x0000, writable 0x0003;
+    /// Disabled, parametric EQ, tone control or 5-band GEQ
+    pub u8, en, set_en: 1, 0;
+}
+
+checked_setters!{
+    DapAudioEq {
+        try_set_en => set_en(u8): 0..=3,
+    }
+}
+
+register!{
+    DapSgtlSurround(DAP_SGTL_SURROUND): 0x010A, reset 0x0040, writable 0x0073;
+    /// Freescale surround width
+    pub u8, width_control, set_width_control: 6, 4;
+    /// Disabled, mono input or stereo input surround
+    pub u8, select, set_select: 1, 0;
+}
+
+checked_setters!{
+    DapSgtlSurround {
+        try_set_width_control => set_width_control(u8): 0..=7,
+        try_set_select => set_select(u8): 0..=3,
+    }
+}
+
+register!{
+    DapFilterCoefAccess(DAP_FILTER_COEF_ACCESS): 0x010C, reset 0x0000, writable 0x01FF;
+    /// Load the coefficients into the filter selected by INDEX
+    pub wr, set_wr: 8;
+    /// Parametric EQ filter to load
+    pub u8, index, set_index: 7, 0;
+}
+
+register!{
+    DapCoefWrB0Msb(DAP_COEF_WR_B0_MSB): 0x010E, reset 0x0000, writable 0xFFFF;
+    /// Bits 19:4 of the B0 coefficient
+    pub u16, msb, set_msb: 15, 0;
+}
+
+register!{
+    DapCoefWrB0Lsb(DAP_COEF_WR_B0_LSB): 0x0110, reset 0x0000, writable 0x000F;
+    /// Bits 3:0 of the B0 coefficient
+    pub u8, lsb, set_lsb: 3, 0;
+}
+
+checked_setters!{
+    DapCoefWrB0Lsb {
+        try_set_lsb => set_lsb(u8): 0x0..=0xF,
+    }
+}
+
+register!{
+    DapAudioEqBassBand0(DAP_AUDIO_EQ_BASS_BAND0): 0x0116, reset 0x002F, writable 0x007F;
+    /// Bass band (115 Hz) level, 0x2F is 0 dB
+    pub u8, volume, set_volume: 6, 0;
+}
+
+checked_setters!{
+    DapAudioEqBassBand0 {
+        try_set_volume => set_volume(u8): 0x00..=0x5F,
+    }
+}
+
+register!{
+    DapAudioEqBand1(DAP_AUDIO_EQ_BAND1): 0x0118, reset 0x002F, writable 0x007F;
+    /// Band 1 (330 Hz) level, 0x2F is 0 dB
+    pub u8, volume, set_volume: 6, 0;
+}
+
+checked_setters!{
+    DapAudioEqBand1 {
+        try_set_volume => set_volume(u8): 0x00..=0x5F,
+    }
+}
+
+register!{
+    DapAudioEqBand2(DAP_AUDIO_EQ_BAND2): 0x011A, reset 0x002F, writable 0x007F;
+    /// Band 2 (990 Hz) level, 0x2F is 0 dB
+    pub u8, volume, set_volume: 6, 0;
+}
+
+checked_setters!{
+    DapAudioEqBand2 {
+        try_set_volume => set_volume(u8): 0x00..=0x5F,
+    }
+}
+
+register!{
+    DapAudioEqBand3(DAP_AUDIO_EQ_BAND3): 0x011C, reset 0x002F, writable 0x007F;
+    /// Band 3 (3 kHz) level, 0x2F is 0 dB
+    pub u8, volume, set_volume: 6, 0;
+}
+
+checked_setters!{
+    DapAudioEqBand3 {
+        try_set_volume => set_volume(u8): 0x00..=0x5F,
+    }
+}
+
+register!{
+    DapAudioEqTrebleBand4(DAP_AUDIO_EQ_TREBLE_BAND4): 0x011E, reset 0x002F, writable 0x007F;
+    /// Treble band (9.9 kHz) level, 0x2F is 0 dB
+    pub u8, volume, set_volume: 6, 0;
+}
+
+checked_setters!{
+    DapAudioEqTrebleBand4 {
+        try_set_volume => set_volume(u8): 0x00..=0x5F,
+    }
+}
+
+register!{
+    DapMainChan(DAP_MAIN_CHAN): 0x0120, reset 0x8000, writable 0xFFFF;
+    /// DAP main channel volume, 0x8000 is 100%
+    pub u16, vol, set_vol: 15, 0;
+}
+
+register!{
+    DapMixChan(DAP_MIX_CHAN): 0x0122, reset 0x0000, writable 0xFFFF;
+    /// DAP mix channel volume, 0x8000 is 100%
+    pub u16, vol, set_vol: 15, 0;
+}
+
+register!{
+    DapAvcCtrl(DAP_AVC_CTRL): 0x0124, reset 0x5100, writable 0x3321;
+    /// Maximum gain the AVC can apply: 0, 6 or 12 dB
+    pub u8, max_gain, set_max_gain: 13, 12;
+    /// Integrator response of the AVC
+    pub u8, lbi_response, set_lbi_response: 9, 8;
+    /// Hard limit instead of compressing above the threshold
+    pub hard_limit_en, set_hard_limit_en: 5;
+    /// Enable automatic volume control (AVC)
+    pub en, set_en: 0;
+}
+
+checked_setters!{
+    DapAvcCtrl {
+        try_set_max_gain => set_max_gain(u8): 0..=2,
+        try_set_lbi_response => set_lbi_response(u8): 0..=3,
+    }
+}
+
+register!{
+    DapAvcThreshold(DAP_AVC_THRESHOLD): 0x0126, reset 0x1473, writable 0xFFFF;
+    /// AVC threshold
+    pub u16, thresh, set_thresh: 15, 0;
+}
+
+register!{
+    DapAvcAttack(DAP_AVC_ATTACK): 0x0128, reset 0x0028, writable 0x0FFF;
+    /// AVC attack rate
+    pub u16, rate, set_rate: 11, 0;
+}
+
+checked_setters!{
+    DapAvcAttack {
+        try_set_rate => set_rate(u16): 0x000..=0xFFF,
+    }
+}
+
+register!{
+    DapAvcDecay(DAP_AVC_DECAY): 0x012A, reset 0x0050, writable 0x0FFF;
+    /// AVC decay rate
+    pub u16, rate, set_rate: 11, 0;
+}
+
+checked_setters!{
+    DapAvcDecay {
+        try_set_rate => set_rate(u16): 0x000..=0xFFF,
+    }
+}
+
+register!{
+    DapCoefWrB1Msb(DAP_COEF_WR_B1_MSB): 0x012C, reset 0x0000, writable 0xFFFF;
+    /// Bits 19:4 of the B1 coefficient
+    pub u16, msb, set_msb: 15, 0;
+}
+
+register!{
+    DapCoefWrB1Lsb(DAP_COEF_WR_B1_LSB): 0x012E, reset 0x0000, writable 0x000F;
+    /// Bits 3:0 of the B1 coefficient
+    pub u8, lsb, set_lsb: 3, 0;
+}
+
+checked_setters!{
+    DapCoefWrB1Lsb {
+        try_set_lsb => set_lsb(u8): 0x0..=0xF,
+    }
+}
+
+register!{
+    DapCoefWrB2Msb(DAP_COEF_WR_B2_MSB): 0x0130, reset 0x0000, writable 0xFFFF;
+    /// Bits 19:4 of the B2 coefficient
+    pub u16, msb, set_msb: 15, 0;
+}
+
+register!{
+    DapCoefWrB2Lsb(DAP_COEF_WR_B2_LSB): 0x0132, reset 0x0000, writable 0x000F;
+    /// Bits 3:0 of the B2 coefficient
+    pub u8, lsb, set_lsb: 3, 0;
+}
+
+checked_setters!{
+    DapCoefWrB2Lsb {
+        try_set_lsb => set_lsb(u8): 0x0..=0xF,
+    }
+}
+
+register!{
+    DapCoefWrA1Msb(DAP_COEF_WR_A1_MSB): 0x0134, reset 0x0000, writable 0xFFFF;
+    /// Bits 19:4 of the A1 coefficient
+    pub u16, msb, set_msb: 15, 0;
+}
+
+register!{
+    DapCoefWrA1Lsb(DAP_COEF_WR_A1_LSB): 0x0136, reset 0x0000, writable 0x000F;
+    /// Bits 3:0 of the A1 coefficient
+    pub u8, lsb, set_lsb: 3, 0;
+}
+
+checked_setters!{
+    DapCoefWrA1Lsb {
+        try_set_lsb => set_lsb(u8): 0x0..=0xF,
+    }
+}
+
+register!{
+    DapCoefWrA2Msb(DAP_COEF_WR_A2_MSB): 0x0138, reset 0x0000, writable 0xFFFF;
+    /// Bits 19:4 of the A2 coefficient
+    pub u16, msb, set_msb: 15, 0;
+}
+
+register!{
+    DapCoefWrA2Lsb(DAP_COEF_WR_A2_LSB): 0x013A, reset 0x0000, writable 0x000F;
+    /// Bits 3:0 of the A2 coefficient
+    pub u8, lsb, set_lsb: 3, 0;
+}
+
+checked_setters!{
+    DapCoefWrA2Lsb {
+        try_set_lsb => set_lsb(u8): 0x0..=0xF,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use self::std::string::String;
+    use super::*;
+
+    /// Bits covered by a field
+    fn field_mask(hi: u32, lo: u32) -> u16 {
+        (((1u32 << (hi - lo + 1)) - 1) << lo) as u16
+    }
+
+    /// Keeps track of the bits claimed by the fields of a register
+    struct Fields {
+        register: &'static str,
+        claimed: u16,
+    }
+
+    impl Fields {
+        fn new(register: &'static str) -> Self {
+            Fields { register, claimed: 0 }
+        }
+
+        fn claim(&mut self, field: &str, mask: u16, access_mask: u16) {
+            assert_eq!(0, self.claimed & mask, "{}.{} overlaps another field", self.register, field);
+            assert_eq!(mask, access_mask & mask, "{}.{} has the wrong access", self.register, field);
+            self.claimed |= mask;
+        }
+
+        fn finish<R: I2cRegister>(self) {
+            assert_eq!(R::WRITABLE_MASK | R::READ_ONLY_MASK, self.claimed,
+                       "{} has bits without fields", self.register);
+            assert_eq!(0, R::WRITABLE_MASK & R::READ_ONLY_MASK, "{}", self.register);
+            assert_eq!(Some(R::WRITABLE_MASK), register_index(R::ADDR).map(|index| REGISTER_MAP[index].2),
+                       "{} is missing in the register map", self.register);
+        }
+    }
+
+    /// Check one field against its datasheet position and width
+    macro_rules! check_field {
+        ($reg:ident, $fields:ident, ro bool $get:ident: $bit:literal) => {{
+            let mask = field_mask($bit, $bit);
+            $fields.claim(stringify!($get), mask, $reg::READ_ONLY_MASK);
+            assert!($reg::new(mask).$get());
+            assert!(!$reg::new(!mask).$get());
+        }};
+        ($reg:ident, $fields:ident, ro $ty:ident $get:ident: $hi:literal, $lo:literal) => {{
+            let mask = field_mask($hi, $lo);
+            $fields.claim(stringify!($get), mask, $reg::READ_ONLY_MASK);
+            let max: $ty = $reg::new(mask).$get();
+            assert_eq!(u32::from(mask >> $lo), u32::from(max), "{} truncated", stringify!($get));
+            assert_eq!(0, $reg::new(!mask).$get());
+        }};
+        ($reg:ident, $fields:ident, rw bool $get:ident, $set:ident: $bit:literal) => {{
+            let mask = field_mask($bit, $bit);
+            $fields.claim(stringify!($get), mask, $reg::WRITABLE_MASK);
+            let mut register = $reg::new(0);
+            register.$set(true);
+            assert_eq!(mask, register.to_inner(), "{}", stringify!($set));
+            let mut register = $reg::new(0xFFFF);
+            register.$set(false);
+            assert_eq!(!mask, register.to_inner(), "{}", stringify!($set));
+            assert!($reg::new(mask).$get());
+        }};
+        ($reg:ident, $fields:ident, rw enum $get:ident, $set:ident: $hi:literal, $lo:literal) => {{
+            let mask = field_mask($hi, $lo);
+            $fields.claim(stringify!($get), mask, $reg::WRITABLE_MASK);
+            let mut decoded = 0;
+            for raw in 0..=(mask >> $lo) {
+                if let Ok(value) = $reg::new(raw << $lo).$get() {
+                    let mut register = $reg::new(0xFFFF);
+                    register.$set(value);
+                    assert_eq!(!mask | raw << $lo, register.to_inner(), "{}", stringify!($set));
+                    decoded += 1;
+                }
+            }
+            assert!(decoded > 1, "{} decodes nothing", stringify!($get));
+        }};
+        ($reg:ident, $fields:ident, rw $ty:ident $get:ident, $set:ident: $hi:literal, $lo:literal) => {{
+            let mask = field_mask($hi, $lo);
+            $fields.claim(stringify!($get), mask, $reg::WRITABLE_MASK);
+            let max = <$ty>::try_from(u32::from(mask >> $lo))
+                .expect(concat!(stringify!($get), " truncated"));
+            let mut register = $reg::new(0);
+            register.$set(max);
+            assert_eq!(mask, register.to_inner(), "{}", stringify!($set));
+            let mut register = $reg::new(0xFFFF);
+            register.$set(0);
+            assert_eq!(!mask, register.to_inner(), "{}", stringify!($set));
+            let value: $ty = $reg::new(mask).$get();
+            assert_eq!(max, value, "{}", stringify!($get));
+        }};
+    }
+
+    macro_rules! conformance {
+        ($(
+            $(#[$attr:meta])*
+            $reg:ident {
+                $($access:ident $ty:ident $get:ident $(, $set:ident)?: $hi:literal $(, $lo:literal)?;)*
+            }
+        )*) => {
+            let mut checked = 0;
+            $(
+                let mut fields = Fields::new(stringify!($reg));
+                $(check_field!($reg, fields, $access $ty $get $(, $set)?: $hi $(, $lo)?);)*
+                fields.finish::<$reg>();
+                checked += 1;
+            )*
+            assert_eq!(REGISTER_MAP.len(), checked);
+        };
+    }
+
+    /// Field layout from the datasheet register descriptions
+    #[test]
+    fn test_register_conformance() {
+        conformance! {
+            // CHIP_ID
+            ChipId {
+                ro u8 partid: 15, 8;
+                ro u8 revid: 7, 0;
+            }
+            // CHIP_DIG_POWER
+            ChipDigPower {
+                rw bool adc_powerup, set_adc_powerup: 6;
+                rw bool dac_powerup, set_dac_powerup: 5;
+                rw bool dap_powerup, set_dap_powerup: 4;
+                rw bool i2s_out_powerup, set_i2s_out_powerup: 1;
+                rw bool i2s_in_powerup, set_i2s_in_powerup: 0;
+            }
+            // CHIP_CLK_CTRL
+            ChipClkCtrl {
+                rw enum rate_mode, set_rate_mode: 5, 4;
+                rw enum sys_fs, set_sys_fs: 3, 2;
+                rw enum mclk_freq, set_mclk_freq: 1, 0;
+            }
+            // CHIP_I2S_CTRL
+            ChipI2sCtrl {
+                rw bool sclkfreq, set_sclkfreq: 8;
+                rw bool ms, set_ms: 7;
+                rw bool sclk_inv, set_sclk_inv: 6;
+                rw enum dlen, set_dlen: 5, 4;
+                rw enum i2s_mode, set_i2s_mode: 3, 2;
+                rw bool lralign, set_lralign: 1;
+                rw bool lrpol, set_lrpol: 0;
+            }
+            // CHIP_SSS_CTRL
+            ChipSssCtrl {
+                rw bool dap_mix_lrswap, set_dap_mix_lrswap: 14;
+                rw bool dap_lrswap, set_dap_lrswap: 13;
+                rw bool dac_lrswap, set_dac_lrswap: 12;
+                rw bool i2s_lrswap, set_i2s_lrswap: 10;
+                rw enum dap_mix_select, set_dap_mix_select: 9, 8;
+                rw enum dap_select, set_dap_select: 7, 6;
+                rw enum dac_select, set_dac_select: 5, 4;
+                rw enum i2s_select, set_i2s_select: 1, 0;
+            }
+            // CHIP_ADCDAC_CTRL
+            ChipAdcdacCtrl {
+                ro bool vol_busy_dac_right: 13;
+                ro bool vol_busy_dac_left: 12;
+                rw bool vol_ramp_en, set_vol_ramp_en: 9;
+                rw bool vol_expo_ramp, set_vol_expo_ramp: 8;
+                rw bool dac_mute_right, set_dac_mute_right: 3;
+                rw bool dac_mute_left, set_dac_mute_left: 2;
+                rw bool adc_hpf_freeze, set_adc_hpf_freeze: 1;
+                rw bool adc_hpf_bypass, set_adc_hpf_bypass: 0;
+            }
+            // CHIP_DAC_VOL
+            ChipDacVol {
+                rw u8 dac_vol_right, set_dac_vol_right: 15, 8;
+                rw u8 dac_vol_left, set_dac_vol_left: 7, 0;
+            }
+            // CHIP_PAD_STRENGTH
+            ChipPadStrength {
+                rw u8 i2s_lrclk, set_i2s_lrclk: 9, 8;
+                rw u8 i2s_sclk, set_i2s_sclk: 7, 6;
+                rw u8 i2s_dout, set_i2s_dout: 5, 4;
+                rw u8 ctrl_data, set_ctrl_data: 3, 2;
+                rw u8 ctrl_clk, set_ctrl_clk: 1, 0;
+            }
+            // CHIP_ANA_ADC_CTRL
+            ChipAnaAdcCtrl {
+                rw bool adc_vol_m6db, set_adc_vol_m6db: 8;
+                rw u8 adc_vol_right, set_adc_vol_right: 7, 4;
+                rw u8 adc_vol_left, set_adc_vol_left: 3, 0;
+            }
+            // CHIP_ANA_HP_CTRL
+            ChipAnaHpCtrl {
+                rw u8 hp_vol_right, set_hp_vol_right: 14, 8;
+                rw u8 hp_vol_left, set_hp_vol_left: 6, 0;
+            }
+            // CHIP_ANA_CTRL
+            ChipAnaCtrl {
+                rw bool mute_lo, set_mute_lo: 8;
+                rw bool select_hp, set_select_hp: 6;
+                rw bool en_zcd_hp, set_en_zcd_hp: 5;
+                rw bool mute_hp, set_mute_hp: 4;
+                rw bool select_adc, set_select_adc: 2;
+                rw bool en_zcd_adc, set_en_zcd_adc: 1;
+                rw bool mute_adc, set_mute_adc: 0;
+            }
+            // CHIP_REF_CTRL
+            ChipRefCtrl {
+                rw u8 vag_val, set_vag_val: 8, 4;
+                rw u8 bias_ctrl, set_bias_ctrl: 3, 1;
+                rw bool small_pop, set_small_pop: 0;
+            }
+            // CHIP_MIC_CTRL
+            ChipMicCtrl {
+                rw u8 bias_resistor, set_bias_resistor: 9, 8;
+                rw u8 bias_volt, set_bias_volt: 6, 4;
+                rw u8 gain, set_gain: 1, 0;
+            }
+            // CHIP_LINREG_CTRL
+            ChipLinregCtrl {
+                rw bool vdcc_man_assn, set_vdcc_man_assn: 6;
+                rw bool vdcc_assn_ovrd, set_vdcc_assn_ovrd: 5;
+                rw u8 d_programming, set_d_programming: 3, 0;
+            }
+            // CHIP_LINE_OUT_CTRL
+            ChipLineOutCtrl {
+                rw u8 out_current, set_out_current: 11, 8;
+                rw u8 lo_vagcntrl, set_lo_vagcntrl: 5, 0;
+            }
+            // CHIP_ANA_POWER
+            ChipAnaPower {
+                rw bool dac_mono, set_dac_mono: 14;
+                rw bool linreg_simple_powerup, set_linreg_simple_powerup: 13;
+                rw bool startup_powerup, set_startup_powerup: 12;
+                rw bool vddc_chrgpmp_powerup, set_vddc_chrgpmp_powerup: 11;
+                rw bool pll_powerup, set_pll_powerup: 10;
+                rw bool linreg_d_powerup, set_linreg_d_powerup: 9;
+                rw bool vcoamp_powerup, set_vcoamp_powerup: 8;
+                rw bool vag_powerup, set_vag_powerup: 7;
+                rw bool adc_mono, set_adc_mono: 6;
+                rw bool reftop_powerup, set_reftop_powerup: 5;
+                rw bool headphone_powerup, set_headphone_powerup: 4;
+                rw bool dac_powerup, set_dac_powerup: 3;
+                rw bool capless_headphone_powerup, set_capless_headphone_powerup: 2;
+                rw bool adc_powerup, set_adc_powerup: 1;
+                rw bool lineout_powerup, set_lineout_powerup: 0;
+            }
+            // CHIP_PLL_CTRL
+            ChipPllCtrl {
+                rw u8 int_divisor, set_int_divisor: 15, 11;
+                rw u16 frac_divisor, set_frac_divisor: 10, 0;
+            }
+            // CHIP_LINE_OUT_VOL
+            ChipLineOutVol {
+                rw u8 lo_vol_right, set_lo_vol_right: 12, 8;
+                rw u8 lo_vol_left, set_lo_vol_left: 4, 0;
+            }
+            // CHIP_CLK_TOP_CTRL
+            ChipClkTopCtrl {
+                rw bool enable_int_osc, set_enable_int_osc: 11;
+                rw bool input_freq_div2, set_input_freq_div2: 3;
+            }
+            // CHIP_ANA_STATUS
+            ChipAnaStatus {
+                ro bool lrshort_sts: 9;
+                ro bool cshort_sts: 8;
+                ro bool pll_is_locked: 4;
+            }
+            // CHIP_ANA_TEST1
+            ChipAnaTest1 {
+                rw u8 hp_iall_adj, set_hp_iall_adj: 13, 12;
+                rw u8 hp_i1_adj, set_hp_i1_adj: 11, 10;
+                rw u8 hp_antipop, set_hp_antipop: 9, 8;
+                rw bool hp_classab, set_hp_classab: 7;
+                rw bool hp_hold_gnd_center, set_hp_hold_gnd_center: 6;
+                rw bool hp_hold_gnd, set_hp_hold_gnd: 5;
+                rw bool vag_doub_current, set_vag_doub_current: 4;
+                rw bool vag_classa, set_vag_classa: 3;
+                rw bool tm_adcin_tohp, set_tm_adcin_tohp: 2;
+                rw bool tm_select_mic, set_tm_select_mic: 1;
+                rw bool testmode, set_testmode: 0;
+            }
+            // CHIP_ANA_TEST2
+            ChipAnaTest2 {
+                rw bool lineout_to_vdda, set_lineout_to_vdda: 14;
+                rw bool spare, set_spare: 13;
+                rw bool monomode_dac, set_monomode_dac: 12;
+                rw bool vco_tune_again, set_vco_tune_again: 11;
+                rw bool lo_pass_mastervag, set_lo_pass_mastervag: 10;
+                rw bool invert_dac_sample_clock, set_invert_dac_sample_clock: 9;
+                rw bool invert_dac_data_timing, set_invert_dac_data_timing: 8;
+                rw bool dac_extend_rtz, set_dac_extend_rtz: 7;
+                rw bool dac_double_i, set_dac_double_i: 6;
+                rw bool dac_dis_rtz, set_dac_dis_rtz: 5;
+                rw bool dac_classa, set_dac_classa: 4;
+                rw bool invert_adc_sample_clock, set_invert_adc_sample_clock: 3;
+                rw bool invert_adc_data_timing, set_invert_adc_data_timing: 2;
+                rw bool adc_lessi, set_adc_lessi: 1;
+                rw bool adc_ditheroff, set_adc_ditheroff: 0;
+            }
+            // CHIP_SHORT_CTRL
+            ChipShortCtrl {
+                rw u8 lvladjr, set_lvladjr: 14, 12;
+                rw u8 lvladjl, set_lvladjl: 10, 8;
+                rw u8 lvladjc, set_lvladjc: 6, 4;
+                rw u8 mode_lr, set_mode_lr: 3, 2;
+                rw u8 mode_cm, set_mode_cm: 1, 0;
+            }
+            // DAP_CONTROL
+            DapControl {
+                rw bool mix_en, set_mix_en: 4;
+                rw bool dap_en, set_dap_en: 0;
+            }
+            // DAP_PEQ
+            DapPeq {
+                rw u8 en, set_en: 2, 0;
+            }
+            // DAP_BASS_ENHANCE
+            DapBassEnhance {
+                rw bool bypass_hpf, set_bypass_hpf: 8;
+                rw u8 cutoff, set_cutoff: 6, 4;
+                rw bool en, set_en: 0;
+            }
+            // DAP_BASS_ENHANCE_CTRL
+            DapBassEnhanceCtrl {
+                rw u8 lr_level, set_lr_level: 13, 8;
+                rw u8 bass_level, set_bass_level: 6, 0;
+            }
+            // DAP_AUDIO_EQ
+            DapAudioEq {
+                rw u8 en, set_en: 1, 0;
+            }
+            // DAP_SGTL_SURROUND
+            DapSgtlSurround {
+                rw u8 width_control, set_width_control: 6, 4;
+                rw u8 select, set_select: 1, 0;
+            }
+            // DAP_FILTER_COEF_ACCESS
+            DapFilterCoefAccess {
+                rw bool wr, set_wr: 8;
+                rw u8 index, set_index: 7, 0;
+            }
+            // DAP_COEF_WR_B0_MSB
+            DapCoefWrB0Msb {
+                rw u16 msb, set_msb: 15, 0;
+            }
+            // DAP_COEF_WR_B0_LSB
+            DapCoefWrB0Lsb {
+                rw u8 lsb, set_lsb: 3, 0;
+            }
+            // DAP_AUDIO_EQ_BASS_BAND0
+            DapAudioEqBassBand0 {
+                rw u8 volume, set_volume: 6, 0;
+            }
+            // DAP_AUDIO_EQ_BAND1
+            DapAudioEqBand1 {
+                rw u8 volume, set_volume: 6, 0;
+            }
+            // DAP_AUDIO_EQ_BAND2
+            DapAudioEqBand2 {
+                rw u8 volume, set_volume: 6, 0;
+            }
+            // DAP_AUDIO_EQ_BAND3
+            DapAudioEqBand3 {
+                rw u8 volume, set_volume: 6, 0;
+            }
+            // DAP_AUDIO_EQ_TREBLE_BAND4
+            DapAudioEqTrebleBand4 {
+                rw u8 volume, set_volume: 6, 0;
+            }
+            // DAP_MAIN_CHAN
+            DapMainChan {
+                rw u16 vol, set_vol: 15, 0;
+            }
+            // DAP_MIX_CHAN
+            DapMixChan {
+                rw u16 vol, set_vol: 15, 0;
+            }
+            // DAP_AVC_CTRL
+            DapAvcCtrl {
+                rw u8 max_gain, set_max_gain: 13, 12;
+                rw u8 lbi_response, set_lbi_response: 9, 8;
+                rw bool hard_limit_en, set_hard_limit_en: 5;
+                rw bool en, set_en: 0;
+            }
+            // DAP_AVC_THRESHOLD
+            DapAvcThreshold {
+                rw u16 thresh, set_thresh: 15, 0;
+            }
+            // DAP_AVC_ATTACK
+            DapAvcAttack {
+                rw u16 rate, set_rate: 11, 0;
+            }
+            // DAP_AVC_DECAY
+            DapAvcDecay {
+                rw u16 rate, set_rate: 11, 0;
+            }
+            // DAP_COEF_WR_B1_MSB
+            DapCoefWrB1Msb {
+                rw u16 msb, set_msb: 15, 0;
+            }
+            // DAP_COEF_WR_B1_LSB
+            DapCoefWrB1Lsb {
+                rw u8 lsb, set_lsb: 3, 0;
+            }
+            // DAP_COEF_WR_B2_MSB
+            DapCoefWrB2Msb {
+                rw u16 msb, set_msb: 15, 0;
+            }
+            // DAP_COEF_WR_B2_LSB
+            DapCoefWrB2Lsb {
+                rw u8 lsb, set_lsb: 3, 0;
+            }
+            // DAP_COEF_WR_A1_MSB
+            DapCoefWrA1Msb {
+                rw u16 msb, set_msb: 15, 0;
+            }
+            // DAP_COEF_WR_A1_LSB
+            DapCoefWrA1Lsb {
+                rw u8 lsb, set_lsb: 3, 0;
+            }
+            // DAP_COEF_WR_A2_MSB
+            DapCoefWrA2Msb {
+                rw u16 msb, set_msb: 15, 0;
+            }
+            // DAP_COEF_WR_A2_LSB
+            DapCoefWrA2Lsb {
+                rw u8 lsb, set_lsb: 3, 0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_register_macro() {
+        assert_eq!(0x0000, ChipId::register_addr());
+        assert_eq!(0xA0, ChipId::reset().partid());
+        assert_eq!(0x0000, ChipId::reserved_mask());
+
+        assert_eq!(0x0036, ChipAnaStatus::ADDR);
+        assert_eq!(0xFCEF, ChipAnaStatus::reserved_mask());
+        assert!(!ChipAnaStatus::reset().pll_is_locked());
+
+        // read_only defaults to none
+        assert_eq!(0x0000, ChipDigPower::READ_ONLY_MASK);
+        assert_eq!(0xFF8C, ChipDigPower::reserved_mask());
+        assert_eq!(0x0073, writable_mask(ChipDigPower::ADDR));
+    }
+
+    #[test]
+    fn test_field_enums() {
+        for value in 0..4 {
+            assert_eq!(Ok(value), SampleRate::try_from(value).map(u8::from));
+            assert_eq!(Ok(value), RateMode::try_from(value).map(u8::from));
+            assert_eq!(Ok(value), MclkRatio::try_from(value).map(u8::from));
+            assert_eq!(Ok(value), WordLength::try_from(value).map(u8::from));
+        }
+        assert_eq!(Err(Reserved(4)), SampleRate::try_from(4));
+        assert_eq!(Err(Reserved(3)), I2sMode::try_from(3));
+        assert_eq!(Err(Reserved(2)), Source::try_from(2));
+        assert_eq!(Err(Reserved(3)), DapSource::try_from(3));
+
+        let mut clk_ctrl = ChipClkCtrl::new(ChipClkCtrl::RESET);
+        assert_eq!(Ok(SampleRate::Hz48000), clk_ctrl.sys_fs());
+        clk_ctrl.set_sys_fs(SampleRate::Hz96000);
+        clk_ctrl.set_rate_mode(RateMode::Div6);
+        clk_ctrl.set_mclk_freq(MclkRatio::Pll);
+        assert_eq!(0x003F, clk_ctrl.to_inner());
+
+        let mut i2s_ctrl = ChipI2sCtrl::new(0x000C);
+        assert_eq!(Err(Reserved(3)), i2s_ctrl.i2s_mode());
+        i2s_ctrl.set_i2s_mode(I2sMode::RightJustified);
+        assert_eq!(Ok(I2sMode::RightJustified), i2s_ctrl.i2s_mode());
+
+        // Only the DAC and I2S_DOUT take the DAP as source
+        let sss_ctrl = ChipSssCtrl::new(0x03F3);
+        assert_eq!(Ok(Source::Dap), sss_ctrl.dac_select());
+        assert_eq!(Ok(Source::Dap), sss_ctrl.i2s_select());
+        assert_eq!(Err(Reserved(3)), sss_ctrl.dap_select());
+        assert_eq!(Err(Reserved(3)), sss_ctrl.dap_mix_select());
+        let mut sss_ctrl = ChipSssCtrl::new(0);
+        sss_ctrl.set_dap_select(DapSource::I2sIn);
+        sss_ctrl.set_dap_mix_select(DapSource::I2sIn);
+        assert_eq!(0x0140, sss_ctrl.to_inner());
+        assert_eq!(Ok(DapSource::I2sIn), sss_ctrl.dap_select());
+    }
+
+    #[test]
+    fn test_checked_setters() {
+        let mut ref_ctrl = ChipRefCtrl::new(0);
+        assert_eq!(Ok(()), ref_ctrl.try_set_vag_val(0x1F));
+        assert_eq!(0x1F, ref_ctrl.vag_val());
+        assert_eq!(Err(OutOfRange { register: "CHIP_REF_CTRL", field: "set_vag_val", value: 0x20 }), ref_ctrl.try_set_vag_val(0x20));
+        // Nothing written on error
+        assert_eq!(0x01F0, ref_ctrl.to_inner());
+
+        let mut line_out_ctrl = ChipLineOutCtrl::new(0);
+        assert_eq!(Ok(()), line_out_ctrl.try_set_lo_vagcntrl(0x23));
+        assert!(line_out_ctrl.try_set_lo_vagcntrl(0x24).is_err());
+        assert_eq!(Ok(()), line_out_ctrl.try_set_out_current(0x7));
+        assert!(line_out_ctrl.try_set_out_current(0x5).is_err());
+
+        let mut dac_vol = ChipDacVol::new(0);
+        assert!(dac_vol.try_set_dac_vol_left(0x3B).is_err());
+        assert_eq!(Ok(()), dac_vol.try_set_dac_vol_left(0xFF));
+
+        let mut pll_ctrl = ChipPllCtrl::new(0);
+        assert_eq!(Ok(()), pll_ctrl.try_set_frac_divisor(786));
+        assert_eq!(786, pll_ctrl.frac_divisor());
+        assert!(pll_ctrl.try_set_frac_divisor(0x800).is_err());
+
+        let mut band1 = DapAudioEqBand1::new(0);
+        assert_eq!(Ok(()), band1.try_set_volume(0x5F));
+        assert_eq!(Err(OutOfRange { register: "DAP_AUDIO_EQ_BAND1", field: "set_volume", value: 0x60 }), band1.try_set_volume(0x60));
+        // 0xAF would have come out as 0x2F
+        assert!(band1.try_set_volume(0xAF).is_err());
+        assert_eq!(0x5F, band1.to_inner());
+
+        let mut avc_ctrl = DapAvcCtrl::new(0);
+        assert_eq!(Ok(()), avc_ctrl.try_set_max_gain(2));
+        assert!(avc_ctrl.try_set_max_gain(3).is_err());
+        assert_eq!(0x2000, avc_ctrl.to_inner());
+
+        let mut peq = DapPeq::new(0);
+        assert_eq!(Ok(()), peq.try_set_en(7));
+        assert!(peq.try_set_en(8).is_err());
+
+        let mut b0_lsb = DapCoefWrB0Lsb::new(0);
+        assert!(b0_lsb.try_set_lsb(0x10).is_err());
+        let mut attack = DapAvcAttack::new(0);
+        assert!(attack.try_set_rate(0x1000).is_err());
+
+        let mut pad_strength = ChipPadStrength::new(0);
+        assert_eq!(Ok(()), pad_strength.try_set_i2s_lrclk(3));
+        assert!(pad_strength.try_set_i2s_lrclk(4).is_err());
+        let mut ana_test1 = ChipAnaTest1::new(0);
+        assert!(ana_test1.try_set_hp_antipop(4).is_err());
+        assert_eq!(0x0000, ana_test1.to_inner());
+    }
+
+    #[test]
+    fn test_write_fields() {
+        let mut dump = String::new();
+        // DAP_SELECT 3 is reserved
+        ChipSssCtrl::new(0x10C0).write_fields(&mut dump).unwrap();
+        assert!(dump.contains("    dac_lrswap: true\n"));
+        assert!(dump.contains("    dap_select: reserved 0x3\n    dac_select: Adc\n"));
+    }
+
+    #[test]
+    fn test_register_map_order() {
+        for pair in REGISTER_MAP.windows(2) {
+            assert!(pair[0].0 < pair[1].0, "{:04X} before {:04X}", pair[0].0, pair[1].0);
+        }
     }
 }
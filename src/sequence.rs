@@ -0,0 +1,491 @@
+//! Register writes shared by the blocking and async drivers
+//!
+//! Operations are described as a [`Sequence`] of [`Step`]s, which
+//! the drivers then carry out on their bus.
+
+use core::convert::Infallible;
+
+use crate::clock::Clock;
+use crate::config::{Config, Supply};
+use crate::registers::*;
+use crate::volume::Volume;
+
+/// A single register update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Overwrite the whole register
+    Write { addr: u16, value: u16 },
+    /// Read the register, then write it back with `clear` bits
+    /// cleared and `set` bits set
+    Modify { addr: u16, clear: u16, set: u16 },
+}
+
+impl Step {
+    pub fn write<R: I2cRegister>(register: R) -> Self {
+        Step::Write {
+            addr: R::register_addr(),
+            value: register.to_inner(),
+        }
+    }
+
+    /// `f` may only assign constant values to fields so that the
+    /// affected bits can be found by applying it to all-zero and
+    /// all-one registers.
+    pub fn modify<R, F>(f: F) -> Self
+    where R: I2cRegister,
+          F: Fn(R) -> R
+    {
+        match Self::try_modify(|register| Ok::<R, Infallible>(f(register))) {
+            Ok(step) => step,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like `modify()` for `f` using checked setters
+    pub fn try_modify<R, F, E>(f: F) -> Result<Self, E>
+    where R: I2cRegister,
+          F: Fn(R) -> Result<R, E>
+    {
+        let set = f(R::new(0))?.to_inner();
+        let clear = !f(R::new(0xFFFF))?.to_inner();
+        Ok(Step::Modify {
+            addr: R::register_addr(),
+            clear,
+            set,
+        })
+    }
+
+    pub fn addr(&self) -> u16 {
+        match *self {
+            Step::Write { addr, .. } => addr,
+            Step::Modify { addr, .. } => addr,
+        }
+    }
+
+    /// New register value, given the current one for `Modify`
+    pub fn apply(&self, old: u16) -> u16 {
+        match *self {
+            Step::Write { value, .. } => value,
+            Step::Modify { clear, set, .. } => (old & !clear) | set,
+        }
+    }
+}
+
+/// Maximum number of steps in a [`Sequence`]
+const SEQUENCE_CAPACITY: usize = 32;
+
+/// Ordered register updates
+pub(crate) struct Sequence {
+    steps: [Step; SEQUENCE_CAPACITY],
+    len: usize,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Sequence {
+            steps: [Step::Write { addr: 0, value: 0 }; SEQUENCE_CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, step: Step) {
+        assert!(self.len < SEQUENCE_CAPACITY, "Sequence capacity exceeded");
+        self.steps[self.len] = step;
+        self.len += 1;
+    }
+
+    pub fn write<R: I2cRegister>(&mut self, register: R) {
+        self.push(Step::write(register));
+    }
+
+    pub fn modify<R, F>(&mut self, f: F)
+    where R: I2cRegister,
+          F: Fn(R) -> R
+    {
+        self.push(Step::modify(f));
+    }
+
+    pub fn try_modify<R, F>(&mut self, f: F) -> Result<(), OutOfRange>
+    where R: I2cRegister,
+          F: Fn(R) -> Result<R, OutOfRange>
+    {
+        self.push(Step::try_modify(f)?);
+        Ok(())
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps[..self.len]
+    }
+}
+
+/// 0.8V
+const ANA_GND_BASE: u16 = 800;
+/// 0.025V
+const ANA_GND_STEP: u16 = 25;
+/// With VDDA and VDDIO both over 3.1V VDDC is taken from VDDIO, with
+/// both below the charge pump generates it
+const CHRGPMP_THRESHOLD: u16 = 3100;
+/// 1.6V, D_PROGRAMMING=0
+const LINREG_BASE: u16 = 1600;
+/// 0.05V
+const LINREG_STEP: u16 = 50;
+/// VDDD from the internal regulator, 1.2V
+const LINREG_VDDD: u16 = 1200;
+
+/// Reference voltage setting for half of a supply, VAG_VAL and
+/// LO_VAGCNTRL use the same steps
+fn half_supply_val(supply: u16) -> u8 {
+    ((supply / 2).saturating_sub(ANA_GND_BASE) / ANA_GND_STEP).min(0xFF) as u8
+}
+
+fn both_low(supply: &Supply) -> bool {
+    supply.vdda < CHRGPMP_THRESHOLD && supply.vddio < CHRGPMP_THRESHOLD
+}
+
+fn both_high(supply: &Supply) -> bool {
+    supply.vdda >= CHRGPMP_THRESHOLD && supply.vddio >= CHRGPMP_THRESHOLD
+}
+
+/// Power-up sequence up to the clock setup, run after the chip has
+/// been identified
+pub(crate) fn power_up(config: &Config, clock: &Clock) -> Result<Sequence, OutOfRange> {
+    let mut seq = Sequence::new();
+
+    seq.modify(|mut ana_power: ChipAnaPower| {
+        ana_power.set_reftop_powerup(true);
+        // Enable stereo
+        ana_power.set_dac_mono(true);
+        // ana_power.set_adc_mono(true);
+
+        ana_power
+    });
+    let supply = &config.supply;
+    // Mixed rails get neither and leave the assignment to the chip
+    let charge_pump = both_low(supply);
+    let vddio_assn = both_high(supply);
+    seq.try_modify(|mut linreg: ChipLinregCtrl| {
+        // VDDA & VDDIO both over 3.1V: take VDDC from VDDIO
+        linreg.set_vdcc_assn_ovrd(vddio_assn);
+        linreg.set_vdcc_man_assn(vddio_assn);
+        if supply.vddd.is_none() {
+            linreg.try_set_d_programming(((LINREG_BASE - LINREG_VDDD) / LINREG_STEP) as u8)?;
+        }
+        Ok(linreg)
+    })?;
+    if charge_pump {
+        // The charge pump runs off the internal oscillator
+        seq.modify(|mut clk_top_ctrl: ChipClkTopCtrl| {
+            clk_top_ctrl.set_enable_int_osc(true);
+            clk_top_ctrl
+        });
+    }
+    seq.try_modify(|mut ref_ctrl: ChipRefCtrl| {
+        // VAG=VDDA/2, 0.8..1.575V
+        ref_ctrl.try_set_vag_val(half_supply_val(supply.vdda).min(0x1F))?;
+        ref_ctrl.try_set_bias_ctrl(1)?;
+        Ok(ref_ctrl)
+    })?;
+    seq.try_modify(|mut line_out_ctrl: ChipLineOutCtrl| {
+        // LO_VAGCNTRL=VDDIO/2, 0.8..1.675V
+        line_out_ctrl.try_set_lo_vagcntrl(half_supply_val(supply.vddio).min(0x23))?;
+        // OUT_CURRENT=0.54mA
+        line_out_ctrl.try_set_out_current(0xF)?;
+        Ok(line_out_ctrl)
+    })?;
+    seq.try_modify(|mut short_ctrl: ChipShortCtrl| {
+        short_ctrl.try_set_lvladjr(4)?;
+        short_ctrl.try_set_lvladjl(4)?;
+        short_ctrl.try_set_lvladjc(4)?;
+        short_ctrl.try_set_mode_lr(1)?;
+        short_ctrl.try_set_mode_cm(2)?;
+        Ok(short_ctrl)
+    })?;
+    seq.modify(|mut ana_ctrl: ChipAnaCtrl| {
+        // DAC or LINEIN as the input to HP_OUT
+        ana_ctrl.set_select_hp(config.routing.headphone_line_in);
+        // Unmute
+        ana_ctrl.set_mute_hp(!config.blocks.headphone);
+        ana_ctrl.set_mute_lo(!config.blocks.lineout);
+        ana_ctrl.set_mute_adc(!config.blocks.adc);
+        ana_ctrl.set_en_zcd_hp(true);
+        ana_ctrl
+    });
+
+    seq.modify(|mut ana_power: ChipAnaPower| {
+        // Internal linear regulator unless VDDD is driven externally
+        ana_power.set_linreg_d_powerup(supply.vddd.is_none());
+        ana_power.set_vddc_chrgpmp_powerup(charge_pump);
+
+        // Enable stereo
+        ana_power.set_dac_mono(true);
+
+        ana_power
+    });
+    clock_steps(&mut seq, clock)?;
+    Ok(seq)
+}
+
+/// Rest of the initialization, run once the PLL is locked
+pub(crate) fn enable_blocks(config: &Config) -> Result<Sequence, OutOfRange> {
+    let supply = &config.supply;
+    let mut seq = Sequence::new();
+
+    seq.modify(|mut i2s_ctrl: ChipI2sCtrl| {
+        config.i2s.apply(&mut i2s_ctrl);
+        i2s_ctrl
+    });
+    seq.modify(|mut ana_power: ChipAnaPower| {
+        ana_power.set_startup_powerup(false);
+        ana_power.set_linreg_simple_powerup(supply.vddd.is_none());
+
+        // Power up desired analog blocks
+        ana_power.set_lineout_powerup(config.blocks.lineout);
+        ana_power.set_adc_powerup(config.blocks.adc);
+        ana_power.set_capless_headphone_powerup(config.blocks.headphone);
+        ana_power.set_dac_powerup(config.blocks.dac);
+        ana_power.set_headphone_powerup(config.blocks.headphone);
+        ana_power.set_reftop_powerup(true);
+
+        ana_power.set_vag_powerup(true);
+        ana_power
+    });
+    seq.modify(|mut dap_control: DapControl| {
+        dap_control.set_dap_en(config.blocks.dap);
+        dap_control
+    });
+
+    // Power up desired digital blocks
+    seq.modify(|mut dig_power: ChipDigPower| {
+        dig_power.set_adc_powerup(config.blocks.adc);
+        dig_power.set_dac_powerup(config.blocks.dac);
+        dig_power.set_dap_powerup(config.blocks.dap);
+        dig_power.set_i2s_out_powerup(config.blocks.i2s_out);
+        dig_power.set_i2s_in_powerup(config.blocks.i2s_in);
+        dig_power
+    });
+
+    // Setup routing
+    seq.modify(|mut sss_ctrl: ChipSssCtrl| {
+        sss_ctrl.set_dap_select(config.routing.dap);
+        sss_ctrl.set_dap_mix_select(config.routing.dap_mix);
+        sss_ctrl.set_dac_select(config.routing.dac);
+        sss_ctrl.set_i2s_select(config.routing.i2s_out);
+        sss_ctrl
+    });
+    seq.modify(|mut adcdac_ctrl: ChipAdcdacCtrl| {
+        adcdac_ctrl.set_vol_ramp_en(true);
+        adcdac_ctrl.set_vol_expo_ramp(false);
+        adcdac_ctrl.set_dac_mute_right(false);
+        adcdac_ctrl.set_dac_mute_left(false);
+        adcdac_ctrl
+    });
+
+    // Volume
+    seq.push(hp_vol(config.volumes.headphone)?);
+    seq.push(lineout_vol(config.volumes.lineout)?);
+    seq.push(dac_vol(config.volumes.dac)?);
+
+    Ok(seq)
+}
+
+/// Switch clocking, the PLL runs before it is selected and stops
+/// after it is not anymore
+fn clock_steps(seq: &mut Sequence, clock: &Clock) -> Result<(), OutOfRange> {
+    match clock.pll() {
+        Some(pll) => {
+            seq.modify(|mut clk_top_ctrl: ChipClkTopCtrl| {
+                clk_top_ctrl.set_input_freq_div2(pll.input_freq_div2);
+                clk_top_ctrl
+            });
+            let mut pll_ctrl = ChipPllCtrl::new(0);
+            pll_ctrl.try_set_int_divisor(pll.int_divisor)?;
+            pll_ctrl.try_set_frac_divisor(pll.frac_divisor)?;
+            seq.write(pll_ctrl);
+            seq.modify(|mut ana_power: ChipAnaPower| {
+                ana_power.set_pll_powerup(true);
+                ana_power.set_vcoamp_powerup(true);
+                ana_power
+            });
+            seq.write(clock.clk_ctrl());
+        }
+        None => {
+            seq.write(clock.clk_ctrl());
+            seq.modify(|mut ana_power: ChipAnaPower| {
+                ana_power.set_pll_powerup(false);
+                ana_power.set_vcoamp_powerup(false);
+                ana_power
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Change sample rate or SYS_MCLK
+pub(crate) fn set_clock(clock: &Clock) -> Result<Sequence, OutOfRange> {
+    let mut seq = Sequence::new();
+    clock_steps(&mut seq, clock)?;
+    Ok(seq)
+}
+
+/// Set DAC volume
+pub(crate) fn dac_vol(volume: Volume) -> Result<Step, OutOfRange> {
+    let (left, right) = volume.to_range(0xFC, 0x3C);
+
+    let mut dac_vol = ChipDacVol::new(0);
+    dac_vol.try_set_dac_vol_left(left)?;
+    dac_vol.try_set_dac_vol_right(right)?;
+    Ok(Step::write(dac_vol))
+}
+
+/// Set LINE_OUT volume
+pub(crate) fn lineout_vol(volume: Volume) -> Result<Step, OutOfRange> {
+    let (left, right) = volume.to_range(0, 0x1F);
+
+    let mut line_out_vol = ChipLineOutVol::new(0);
+    line_out_vol.try_set_lo_vol_left(left)?;
+    line_out_vol.try_set_lo_vol_right(right)?;
+    Ok(Step::write(line_out_vol))
+}
+
+/// Set headphones volume
+pub(crate) fn hp_vol(volume: Volume) -> Result<Step, OutOfRange> {
+    let (left, right) = volume.to_range(0x7F, 0);
+
+    let mut ana_hp_ctrl = ChipAnaHpCtrl::new(0);
+    ana_hp_ctrl.try_set_hp_vol_left(left)?;
+    ana_hp_ctrl.try_set_hp_vol_right(right)?;
+    Ok(Step::write(ana_hp_ctrl))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::Mclk;
+    use crate::sim::Simulator;
+
+    #[test]
+    fn test_modify_step() {
+        let step = Step::modify(|mut ana_ctrl: ChipAnaCtrl| {
+            ana_ctrl.set_mute_hp(false);
+            ana_ctrl.set_en_zcd_hp(true);
+            ana_ctrl
+        });
+        assert_eq!(Step::Modify { addr: 0x0024, clear: 0x0010, set: 0x0020 }, step);
+        assert_eq!(0x0121, step.apply(0x0111));
+    }
+
+    /// Registers after running `power_up` and `enable_blocks` on a
+    /// freshly reset codec
+    fn run_init(config: &Config) -> Simulator {
+        let mut sim = Simulator::new();
+        let power_up = power_up(config, &config.clock().unwrap()).unwrap();
+        let enable_blocks = enable_blocks(config).unwrap();
+        for step in power_up.steps().iter().chain(enable_blocks.steps()) {
+            let old = sim.peek(step.addr()).unwrap();
+            sim.poke(step.addr(), step.apply(old)).unwrap();
+        }
+        sim
+    }
+
+    fn run_supply(vdda: u16, vddio: u16, vddd: Option<u16>) -> Simulator {
+        let config = Config::builder()
+            .supply(Supply { vdda, vddio, vddd })
+            .build();
+        assert_eq!(Ok(()), config.validate());
+        run_init(&config)
+    }
+
+    #[test]
+    fn test_supply_3v3() {
+        let sim = run_supply(3300, 3300, None);
+        let linreg = sim.register::<ChipLinregCtrl>();
+        assert!(linreg.vdcc_assn_ovrd() && linreg.vdcc_man_assn());
+        // 1.2V
+        assert_eq!(8, linreg.d_programming());
+        let ana_power = sim.register::<ChipAnaPower>();
+        assert!(!ana_power.vddc_chrgpmp_powerup());
+        assert!(ana_power.linreg_d_powerup());
+        assert!(ana_power.linreg_simple_powerup());
+        assert!(!sim.register::<ChipClkTopCtrl>().enable_int_osc());
+        // Clamped to 1.575V
+        assert_eq!(0x1F, sim.register::<ChipRefCtrl>().vag_val());
+        // 1.65V
+        assert_eq!(0x22, sim.register::<ChipLineOutCtrl>().lo_vagcntrl());
+    }
+
+    #[test]
+    fn test_supply_1v8() {
+        let sim = run_supply(1800, 1800, None);
+        let linreg = sim.register::<ChipLinregCtrl>();
+        assert!(!linreg.vdcc_assn_ovrd());
+        assert_eq!(8, linreg.d_programming());
+        assert!(sim.register::<ChipAnaPower>().vddc_chrgpmp_powerup());
+        assert!(sim.register::<ChipClkTopCtrl>().enable_int_osc());
+        // 0.9V
+        assert_eq!(0x04, sim.register::<ChipRefCtrl>().vag_val());
+        assert_eq!(0x04, sim.register::<ChipLineOutCtrl>().lo_vagcntrl());
+    }
+
+    #[test]
+    fn test_supply_mixed() {
+        // Only one rail over 3.1V: neither the charge pump nor a manual
+        // VDDC assignment
+        let sim = run_supply(3300, 1800, None);
+        let linreg = sim.register::<ChipLinregCtrl>();
+        assert!(!linreg.vdcc_assn_ovrd() && !linreg.vdcc_man_assn());
+        assert!(!sim.register::<ChipAnaPower>().vddc_chrgpmp_powerup());
+        assert!(!sim.register::<ChipClkTopCtrl>().enable_int_osc());
+        assert_eq!(0x1F, sim.register::<ChipRefCtrl>().vag_val());
+        assert_eq!(0x04, sim.register::<ChipLineOutCtrl>().lo_vagcntrl());
+
+        let sim = run_supply(1800, 3300, None);
+        assert!(!sim.register::<ChipLinregCtrl>().vdcc_assn_ovrd());
+        assert!(!sim.register::<ChipAnaPower>().vddc_chrgpmp_powerup());
+        assert_eq!(0x04, sim.register::<ChipRefCtrl>().vag_val());
+        assert_eq!(0x22, sim.register::<ChipLineOutCtrl>().lo_vagcntrl());
+    }
+
+    #[test]
+    fn test_supply_external_vddd() {
+        for &(vdda, vddio) in &[(3300, 3300), (1800, 1800)] {
+            let sim = run_supply(vdda, vddio, Some(1800));
+            // Left at reset
+            assert_eq!(0, sim.register::<ChipLinregCtrl>().d_programming());
+            let ana_power = sim.register::<ChipAnaPower>();
+            assert!(!ana_power.linreg_d_powerup());
+            assert!(!ana_power.linreg_simple_powerup());
+            assert!(!ana_power.startup_powerup());
+        }
+    }
+
+    #[test]
+    fn test_init_capacity() {
+        let config = Config::default();
+        assert!(power_up(&config, &config.clock().unwrap()).unwrap().steps().len() <= SEQUENCE_CAPACITY);
+        assert!(enable_blocks(&config).unwrap().steps().len() <= SEQUENCE_CAPACITY);
+        // Everything optional
+        let config = Config::builder()
+            .supply(Supply { vdda: 1800, vddio: 1800, vddd: None })
+            .mclk(Mclk::Pll(12_000_000))
+            .build();
+        assert!(power_up(&config, &config.clock().unwrap()).unwrap().steps().len() <= SEQUENCE_CAPACITY);
+    }
+
+    #[test]
+    fn test_pll() {
+        let config = Config::builder()
+            .mclk(Mclk::Pll(24_000_000))
+            .build();
+        let sim = run_init(&config);
+        assert!(sim.register::<ChipClkTopCtrl>().input_freq_div2());
+        let pll_ctrl = sim.register::<ChipPllCtrl>();
+        assert_eq!((16, 786), (pll_ctrl.int_divisor(), pll_ctrl.frac_divisor()));
+        let ana_power = sim.register::<ChipAnaPower>();
+        assert!(ana_power.pll_powerup() && ana_power.vcoamp_powerup());
+        assert_eq!(Ok(MclkRatio::Pll), sim.register::<ChipClkCtrl>().mclk_freq());
+
+        let sim = run_init(&Config::default());
+        let ana_power = sim.register::<ChipAnaPower>();
+        assert!(!ana_power.pll_powerup() && !ana_power.vcoamp_powerup());
+    }
+}
@@ -0,0 +1,331 @@
+//! In-memory SGTL5000 for testing drivers without hardware
+//!
+//! [`Simulator`] implements the embedded-hal I2C traits, blocking and
+//! async, and behaves like a codec on the bus: it answers at address
+//! 0x0A (or 0x2A), keeps every register at its datasheet reset value,
+//! ignores writes to read-only bits and rejects register addresses
+//! that are not part of the register map. The PLL reports being
+//! locked as soon as it and its VCO amplifier are powered up, or after
+//! a number of CHIP_ANA_STATUS reads set with
+//! [`Simulator::set_pll_lock_reads`].
+//!
+//! [`CountingDelay`] stands in for a delay provider and adds up the
+//! time it was asked to wait.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{self, ErrorKind, NoAcknowledgeSource, Operation};
+
+use crate::interface::Address;
+use crate::registers::{register_index, reset_values, written_value, ChipAnaPower, ChipAnaStatus, I2cRegister, REGISTER_MAP};
+
+/// The 7-bit address the simulator answers at by default
+pub const I2C_ADDR: u8 = 0b1010;
+
+/// CHIP_ANA_STATUS bit
+const PLL_IS_LOCKED: u16 = 1 << 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimError {
+    /// Nobody answered at this bus address
+    AddressNack(u8),
+    /// This address is not in the SGTL5000 register map
+    UnknownRegister(u16),
+    /// Transfers are made of a 16-bit register address and 16-bit
+    /// values
+    InvalidLength(usize),
+}
+
+impl i2c::Error for SimError {
+    fn kind(&self) -> ErrorKind {
+        match *self {
+            SimError::AddressNack(_) =>
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            SimError::UnknownRegister(_) =>
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            SimError::InvalidLength(_) =>
+                ErrorKind::Other,
+        }
+    }
+}
+
+/// Simulated SGTL5000 on an I2C bus
+pub struct Simulator {
+    /// 7-bit bus address
+    address: u8,
+    values: [u16; REGISTER_MAP.len()],
+    /// Register address for the next read
+    addr: u16,
+    /// CHIP_ANA_STATUS reads that show the PLL unlocked after power-up,
+    /// `None` if it never locks
+    pll_lock_reads: Option<u32>,
+    /// Reads left until the powered-up PLL locks
+    pll_unlocked_reads: Option<u32>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A codec with all registers at their reset values
+    pub fn new() -> Self {
+        Simulator {
+            address: I2C_ADDR,
+            values: reset_values(),
+            addr: 0,
+            pll_lock_reads: Some(0),
+            pll_unlocked_reads: None,
+        }
+    }
+
+    /// A codec with CTRL_ADR0_CS strapped for `address`
+    pub fn with_address(address: Address) -> Self {
+        Simulator {
+            address: address.i2c_addr(),
+            ..Self::new()
+        }
+    }
+
+    fn index(addr: u16) -> Result<usize, SimError> {
+        register_index(addr)
+            .ok_or(SimError::UnknownRegister(addr))
+    }
+
+    /// Current register value
+    pub fn peek(&self, addr: u16) -> Result<u16, SimError> {
+        let index = Self::index(addr)?;
+        Ok(self.values[index])
+    }
+
+    /// Current value of a typed register
+    pub fn register<R: I2cRegister>(&self) -> R {
+        R::new(self.peek(R::ADDR).unwrap_or(R::RESET))
+    }
+
+    /// Let `reads` CHIP_ANA_STATUS reads show the PLL unlocked after it
+    /// is powered up, or never lock it with `None`
+    pub fn set_pll_lock_reads(&mut self, reads: Option<u32>) {
+        self.pll_lock_reads = reads;
+    }
+
+    /// Set a register value including read-only bits, like the
+    /// hardware itself would
+    pub fn poke(&mut self, addr: u16, value: u16) -> Result<(), SimError> {
+        let index = Self::index(addr)?;
+        self.values[index] = value;
+        Ok(())
+    }
+
+    /// Write a register as the bus would, leaving read-only bits
+    /// untouched
+    fn write_register(&mut self, addr: u16, value: u16) -> Result<(), SimError> {
+        let index = Self::index(addr)?;
+        self.values[index] = written_value(index, self.values[index], value);
+
+        if addr == ChipAnaPower::ADDR {
+            let ana_power = ChipAnaPower::new(self.values[index]);
+            self.pll_unlocked_reads = if ana_power.pll_powerup() && ana_power.vcoamp_powerup() {
+                self.pll_lock_reads
+            } else {
+                None
+            };
+            self.update_pll_lock()?;
+        }
+        Ok(())
+    }
+
+    fn update_pll_lock(&mut self) -> Result<(), SimError> {
+        let ana_status = self.peek(ChipAnaStatus::ADDR)?;
+        let ana_status = if self.pll_unlocked_reads == Some(0) {
+            ana_status | PLL_IS_LOCKED
+        } else {
+            ana_status & !PLL_IS_LOCKED
+        };
+        self.poke(ChipAnaStatus::ADDR, ana_status)
+    }
+
+    /// Count down to the PLL lock after CHIP_ANA_STATUS was read
+    fn read_register(&mut self, addr: u16) -> Result<u16, SimError> {
+        let value = self.peek(addr)?;
+        if addr == ChipAnaStatus::ADDR {
+            if let Some(reads) = self.pll_unlocked_reads {
+                self.pll_unlocked_reads = Some(reads.saturating_sub(1));
+                self.update_pll_lock()?;
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl Simulator {
+    /// Register address followed by values for consecutive registers
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SimError> {
+        if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+            return Err(SimError::InvalidLength(bytes.len()));
+        }
+
+        let addr = u16::from(bytes[0]) << 8 | u16::from(bytes[1]);
+        Self::index(addr)?;
+        self.addr = addr;
+        for value in bytes[2..].chunks(2) {
+            let value = u16::from(value[0]) << 8 | u16::from(value[1]);
+            self.write_register(self.addr, value)?;
+            self.addr += 2;
+        }
+        Ok(())
+    }
+
+    /// Values of consecutive registers
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), SimError> {
+        if !buffer.len().is_multiple_of(2) {
+            return Err(SimError::InvalidLength(buffer.len()));
+        }
+
+        for value in buffer.chunks_mut(2) {
+            let v = self.read_register(self.addr)?;
+            value[0] = (v >> 8) as u8;
+            value[1] = v as u8;
+            self.addr += 2;
+        }
+        Ok(())
+    }
+}
+
+impl i2c::ErrorType for Simulator {
+    type Error = SimError;
+}
+
+impl i2c::I2c for Simulator {
+    /// Every write operation starts with a register address
+    fn transaction(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), SimError> {
+        if address != self.address {
+            return Err(SimError::AddressNack(address));
+        }
+
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => self.write_bytes(bytes)?,
+                Operation::Read(buffer) => self.read_bytes(buffer)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl embedded_hal_async::i2c::I2c for Simulator {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), SimError> {
+        i2c::I2c::transaction(self, address, operations)
+    }
+}
+
+/// Delay provider that counts microseconds instead of waiting
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CountingDelay(pub u32);
+
+impl DelayNs for CountingDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0 += ns / 1000;
+    }
+}
+
+impl embedded_hal_async::delay::DelayNs for CountingDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        DelayNs::delay_ns(self, ns)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal::i2c::I2c;
+    use crate::registers::ChipId;
+
+    #[test]
+    fn test_reset_values() {
+        let mut sim = Simulator::new();
+        let mut buf = [0; 2];
+        sim.write_read(I2C_ADDR, &[0x00, 0x00], &mut buf).unwrap();
+        assert_eq!(0xA0, buf[0]);
+        assert_eq!(Ok(0x7060), sim.peek(0x0030));
+        assert_eq!(0xA0, sim.register::<ChipId>().partid());
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut sim = Simulator::new();
+        sim.write(I2C_ADDR, &[0x00, 0x10, 0x12, 0x34]).unwrap();
+        sim.write(I2C_ADDR, &[0x00, 0x10]).unwrap();
+        let mut buf = [0; 2];
+        sim.read(I2C_ADDR, &mut buf).unwrap();
+        assert_eq!([0x12, 0x34], buf);
+    }
+
+    #[test]
+    fn test_auto_increment() {
+        let mut sim = Simulator::new();
+        sim.write(I2C_ADDR, &[0x00, 0x2E, 0x01, 0x02, 0x03, 0x04]).unwrap();
+        assert_eq!(Ok(0x0102), sim.peek(0x002E));
+        assert_eq!(Ok(0x0304), sim.peek(0x0030));
+    }
+
+    #[test]
+    fn test_read_only_bits() {
+        let mut sim = Simulator::new();
+        sim.write(I2C_ADDR, &[0x00, 0x00, 0x12, 0x34]).unwrap();
+        assert_eq!(Ok(0xA011), sim.peek(0x0000));
+        // Only bits 6, 5, 4, 1 and 0 are writable
+        sim.write(I2C_ADDR, &[0x00, 0x02, 0xFF, 0xFF]).unwrap();
+        assert_eq!(Ok(0x0073), sim.peek(0x0002));
+    }
+
+    #[test]
+    fn test_pll_lock() {
+        let mut sim = Simulator::new();
+        assert!(!sim.register::<ChipAnaStatus>().pll_is_locked());
+        // PLL and VCO amplifier
+        sim.write(I2C_ADDR, &[0x00, 0x30, 0x75, 0x60]).unwrap();
+        assert!(sim.register::<ChipAnaStatus>().pll_is_locked());
+        sim.write(I2C_ADDR, &[0x00, 0x30, 0x74, 0x60]).unwrap();
+        assert!(!sim.register::<ChipAnaStatus>().pll_is_locked());
+    }
+
+    #[test]
+    fn test_pll_lock_reads() {
+        let mut sim = Simulator::new();
+        sim.set_pll_lock_reads(Some(2));
+        sim.write(I2C_ADDR, &[0x00, 0x30, 0x75, 0x60]).unwrap();
+        let mut buf = [0; 2];
+        for locked in [false, false, true, true] {
+            sim.write_read(I2C_ADDR, &[0x00, 0x36], &mut buf).unwrap();
+            assert_eq!(locked, buf[1] & 0x10 != 0);
+        }
+
+        sim.set_pll_lock_reads(None);
+        sim.write(I2C_ADDR, &[0x00, 0x30, 0x75, 0x60]).unwrap();
+        for _ in 0..4 {
+            sim.write_read(I2C_ADDR, &[0x00, 0x36], &mut buf).unwrap();
+            assert_eq!(0, buf[1] & 0x10);
+        }
+    }
+
+    #[test]
+    fn test_unknown_register() {
+        let mut sim = Simulator::new();
+        assert_eq!(Err(SimError::UnknownRegister(0x0008)), sim.write(I2C_ADDR, &[0x00, 0x08]));
+        assert_eq!(Err(SimError::UnknownRegister(0x0003)), sim.write(I2C_ADDR, &[0x00, 0x03, 0, 0]));
+        // Running off the end of the map
+        sim.write(I2C_ADDR, &[0x00, 0x06]).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(Err(SimError::UnknownRegister(0x0008)), sim.read(I2C_ADDR, &mut buf));
+    }
+
+    #[test]
+    fn test_address_nack() {
+        let mut sim = Simulator::new();
+        assert_eq!(Err(SimError::AddressNack(0x2A)), sim.write(0x2A, &[0x00, 0x00]));
+        let mut buf = [0; 2];
+        assert_eq!(Err(SimError::AddressNack(0x2A)), sim.read(0x2A, &mut buf));
+    }
+}
@@ -1,6 +1,4 @@
-use core::ops::Sub;
-
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Volume {
     left: u8,
     right: u8,
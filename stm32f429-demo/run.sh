@@ -8,7 +8,7 @@ xargo build --target=thumbv7em-none-eabihf --release \
 killall openocd
 sleep 0.1
 
-BIN=target/thumbv7em-none-eabihf/release/sgtl5000
+BIN=target/thumbv7em-none-eabihf/release/sgtl5000-stm32f429-demo
 openocd \
     -f /usr/share/openocd/scripts/interface/stlink-v2-1.cfg \
     -f /usr/share/openocd/scripts/target/stm32f4x.cfg \
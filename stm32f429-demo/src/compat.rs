@@ -0,0 +1,67 @@
+//! stm32f429-hal implements the embedded-hal 0.2 traits, the driver
+//! takes embedded-hal 1.0 ones
+
+use core::fmt::Debug;
+
+use cortex_m::asm;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::i2c::{self, ErrorKind, ErrorType, Operation, SevenBitAddress};
+
+/// 0.2 blocking I2C as 1.0 `I2c`
+pub struct I2cCompat<I2C>(pub I2C);
+
+#[derive(Debug)]
+pub enum CompatError<E> {
+    I2c(E),
+    /// Only writes, each optionally followed by one read, map to the
+    /// 0.2 traits
+    Unsupported,
+}
+
+impl<E: Debug> i2c::Error for CompatError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<I2C, E> ErrorType for I2cCompat<I2C>
+where I2C: Write<Error=E> + WriteRead<Error=E>,
+      E: Debug
+{
+    type Error = CompatError<E>;
+}
+
+impl<I2C, E> i2c::I2c<SevenBitAddress> for I2cCompat<I2C>
+where I2C: Write<Error=E> + WriteRead<Error=E>,
+      E: Debug
+{
+    fn transaction(&mut self, address: u8, operations: &mut [Operation]) -> Result<(), Self::Error> {
+        let mut operations = operations.iter_mut().peekable();
+        while let Some(operation) = operations.next() {
+            match (operation, operations.peek_mut()) {
+                (Operation::Write(bytes), Some(Operation::Read(buffer))) => {
+                    self.0.write_read(address, bytes, buffer).map_err(CompatError::I2c)?;
+                    operations.next();
+                }
+                (Operation::Write(bytes), _) =>
+                    self.0.write(address, bytes).map_err(CompatError::I2c)?,
+                (Operation::Read(_), _) =>
+                    return Err(CompatError::Unsupported),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Busy-waiting 1.0 `DelayNs`, the HAL has none
+pub struct AsmDelay {
+    pub sysclk_hz: u32,
+}
+
+impl DelayNs for AsmDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        let cycles = u64::from(ns) * u64::from(self.sysclk_hz) / 1_000_000_000;
+        asm::delay(cycles as u32 + 1);
+    }
+}
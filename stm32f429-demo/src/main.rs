@@ -1,24 +1,20 @@
 #![no_std]
 #![feature(used)]
 
-#[macro_use]
-extern crate bitfield;
-extern crate byteorder;
 extern crate cortex_m;
 extern crate cortex_m_rt;
 extern crate cortex_m_semihosting;
 #[macro_use(exception, interrupt)]
 extern crate stm32f429;
 extern crate embedded_hal;
+extern crate embedded_hal_1;
 extern crate stm32f429_hal;
-
-// use byteorder::{ByteOrder, BigEndian};
+extern crate sgtl5000;
 
 use core::cell::RefCell;
 use cortex_m::asm;
 use cortex_m::interrupt::Mutex;
 use stm32f429::{Peripherals, CorePeripherals, SYST};
-use embedded_hal::blocking::*;
 use embedded_hal::digital::OutputPin;
 use stm32f429_hal::time::*;
 use stm32f429_hal::gpio::GpioExt;
@@ -32,31 +28,13 @@ use stm32f429_hal::dma::dma1::s4::DoubleBufferedTransfer;
 use core::fmt::Write;
 use cortex_m_semihosting::hio;
 
-mod volume;
-mod registers;
-use registers::*;
-mod control;
-use control::SGTL5000Control;
-mod error;
-use error::Error;
-
-
-/// https://www.nxp.com/docs/en/data-sheet/SGTL5000.pdf
-struct SGTL5000<I2C: i2c::Read + i2c::Write> {
-    control: SGTL5000Control<I2C>,
-}
+mod compat;
 
-const I2C_ADDR: u8 = 0b1010;
+use compat::{AsmDelay, I2cCompat};
+use sgtl5000::{Address, Config, I2sFormat, SGTL5000};
 
-impl<I2C: i2c::Read<Error=I2CE> + i2c::Write<Error=I2CE>, I2CE> SGTL5000<I2C> {
-    pub fn new(i2c: I2C) -> Result<Self, Error<I2CE>> {
-        let control = SGTL5000Control::new(i2c)?;
-        let mut sgtl5000 = Self {
-            control,
-        };
-        Ok(sgtl5000)
-    }
-}
+/// Sample type of the I2S stream, which also sets up the codec
+type Frame = u16;
 
 fn main() {
     let mut stdout = hio::hstdout().unwrap();
@@ -102,14 +80,19 @@ fn main() {
     writeln!(stdout, "I2S");
     let i2s = I2s::spi2(p.SPI2, sd, ck, ws, clocks, &mut rcc.apb1);
     writeln!(stdout, "I2S output");
-    let mut output = i2s.into_slave_output::<u16>(I2sStandard::Pcm);
+    let mut output = i2s.into_slave_output::<Frame>(I2sStandard::Pcm);
 
     writeln!(stdout, "DMA setup");
     let streams = p.DMA1.split(&mut rcc.ahb1);
     let mut i2s_stream = streams.s4;
 
     writeln!(stdout, "SGTL");
-    let mut sgtl = SGTL5000::new(i2c).unwrap();
+    let config = Config::builder()
+        .i2s(I2sFormat::for_sample::<Frame>())
+        .build();
+    let mut delay = AsmDelay { sysclk_hz: clocks.sysclk().0 };
+    let mut sgtl = SGTL5000::with_config(I2cCompat(i2c), Address::Adr0Low, &config, &mut delay).unwrap();
+    sgtl.control.dump_registers(&mut stdout).unwrap();
 
     let mut last_stats = get_time();
     let mut total_samples_prev = 0usize;
@@ -119,10 +102,10 @@ fn main() {
     let mut freq = 50;
     let mut vo = 0;
     // let mut volume = 0xFF;
-    let mut values = [[0u16; 16384]; 3];
+    let mut values = [[0 as Frame; 16384]; 3];
     let mut next_values = 2;
     // let mut freq = 50;
-    let mut transfer: DoubleBufferedTransfer<u16> =
+    let mut transfer: DoubleBufferedTransfer<Frame> =
         output.dma_transfer(i2s_stream, C0, (&values[0], &values[1]));
     loop {
         led2.set_high();